@@ -29,6 +29,9 @@ enum Commands {
     /// Builds a given webroot.
     Build(BuildArgs),
 
+    /// Checks the internal links in a built webroot, failing if any of them are broken.
+    CheckLinks(CheckLinksArgs),
+
     /// Starts a REPL for crabsoup.
     Repl {
         /// Enables the deprecated functions available to plugins.
@@ -42,6 +45,30 @@ enum Commands {
 struct BuildArgs {
     #[arg(short, long)]
     config: Option<PathBuf>,
+
+    /// Prints the changes that a widget makes to each page.
+    ///
+    /// This can be given more than once. Without a widget name, the changes of every widget are printed.
+    #[arg(long, value_name = "WIDGET", num_args = 0..=1, default_missing_value = "*")]
+    explain_widget: Vec<String>,
+}
+
+#[derive(Parser, Serialize)]
+#[command(version)]
+struct CheckLinksArgs {
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Lists the external links found, which are not checked.
+    #[arg(long)]
+    list_external: bool,
+}
+
+#[derive(Serialize)]
+struct MainArgs<T: Serialize> {
+    command: &'static str,
+    #[serde(flatten)]
+    args: T,
 }
 
 pub fn main() -> Result<()> {
@@ -61,9 +88,12 @@ pub fn main() -> Result<()> {
         .with_env_filter(env_spec)
         .init();
 
-    match cli.command {
+    let success = match cli.command {
         Commands::Build(args) => {
-            CrabsoupLuaContext::new()?.run_main(args)?;
+            CrabsoupLuaContext::new()?.run_main(MainArgs { command: "build", args })?
+        }
+        Commands::CheckLinks(args) => {
+            CrabsoupLuaContext::new()?.run_main(MainArgs { command: "check-links", args })?
         }
         Commands::Repl { plugin } => {
             if plugin {
@@ -71,8 +101,12 @@ pub fn main() -> Result<()> {
             } else {
                 CrabsoupLuaContext::new()?.repl()?;
             }
+            true
         }
-    }
+    };
 
+    if !success {
+        std::process::exit(1);
+    }
     Ok(())
 }
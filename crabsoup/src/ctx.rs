@@ -1,4 +1,7 @@
-use crate::libs::{analyze, base, codec, date, digest, html, log, process, regex, string, sys};
+use crate::libs::{
+    analyze, base, codec, compress, date, digest, highlight, html, image, log, process, regex,
+    string, sys,
+};
 use mlua::{
     ffi::luau_setfflag, prelude::LuaFunction, serde::ser, ChunkMode, Lua, LuaOptions, LuaSerdeExt,
     Result, StdLib, Table, Thread,
@@ -37,9 +40,12 @@ impl CrabsoupLuaContext {
             shared_table.set("analyze", analyze::create_analyze_table(&lua)?)?;
             shared_table.set("baselib", base::create_base_table(&lua)?)?;
             shared_table.set("codecs", codec::create_codec_table(&lua)?)?;
+            shared_table.set("Compress", compress::create_compress_table(&lua)?)?;
             shared_table.set("Date", date::create_date_table(&lua)?)?;
             shared_table.set("Digest", digest::create_digest_table(&lua)?)?;
+            shared_table.set("Highlight", highlight::create_highlight_table(&lua)?)?;
             shared_table.set("HTML", html::create_html_table(&lua)?)?;
+            shared_table.set("Image", image::create_image_table(&lua)?)?;
             shared_table.set("Log", log::create_log_table(&lua)?)?;
             shared_table.set("Process", process::create_process_table(&lua)?)?;
             shared_table.set("Regex", regex::create_regex_table(&lua)?)?;
@@ -91,7 +97,8 @@ impl CrabsoupLuaContext {
         Ok(())
     }
 
-    pub fn run_main(&self, args: impl Serialize) -> Result<()> {
+    /// Runs the `crabsoup` command line application, returning whether the command succeeded.
+    pub fn run_main(&self, args: impl Serialize) -> Result<bool> {
         let mut options = ser::Options::new();
         options.serialize_none_to_null = false;
         options.serialize_unit_to_null = false;
@@ -99,9 +106,9 @@ impl CrabsoupLuaContext {
 
         let value = self.lua.to_value_with(&args, options)?;
         let shared_table = self.lua.named_registry_value::<Table>(SHARED_TABLE_LOC)?;
-        shared_table
+        let success = shared_table
             .get::<_, LuaFunction>("run_main")?
-            .call::<_, ()>(value)?;
-        Ok(())
+            .call::<_, bool>(value)?;
+        Ok(success)
     }
 }
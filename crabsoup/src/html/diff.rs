@@ -0,0 +1,534 @@
+use crate::wyhash::{WyHashBuilder, WyHashMap, WyHashSet};
+use kuchikiki::{NodeData, NodeRef};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    hash::{BuildHasher, Hash, Hasher},
+    rc::Rc,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    /// Whether text nodes that only contain whitespace are left out of the comparison.
+    pub ignore_whitespace: bool,
+    /// Whether elements with the same attributes in a different order are considered equal.
+    pub ignore_attribute_order: bool,
+}
+
+/// A difference between two trees.
+///
+/// Paths of nodes that were removed or moved away are paths in the old tree, and all other paths are paths in the
+/// new tree.
+#[derive(Clone, Debug)]
+pub enum Change {
+    Insert {
+        path: String,
+        node: NodeRef,
+    },
+    Remove {
+        path: String,
+        node: NodeRef,
+    },
+    Move {
+        from: String,
+        path: String,
+        node: NodeRef,
+    },
+    Attribute {
+        path: String,
+        name: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    AttributeOrder {
+        path: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    Text {
+        path: String,
+        old: String,
+        new: String,
+    },
+}
+
+fn node_id(node: &NodeRef) -> usize {
+    Rc::as_ptr(&node.0) as usize
+}
+
+fn is_whitespace(node: &NodeRef) -> bool {
+    node.as_text()
+        .is_some_and(|x| x.borrow().chars().all(|x| x.is_ascii_whitespace()))
+}
+
+/// Whether two nodes can be compared, rather than one being replaced by the other.
+fn is_same_kind(a: &NodeRef, b: &NodeRef) -> bool {
+    match (a.data(), b.data()) {
+        (NodeData::Element(a), NodeData::Element(b)) => *a.name.borrow() == *b.name.borrow(),
+        (NodeData::ProcessingInstruction(a), NodeData::ProcessingInstruction(b)) => {
+            a.borrow().0 == b.borrow().0
+        }
+        (NodeData::Doctype(a), NodeData::Doctype(b)) => {
+            (&a.name, &a.public_id, &a.system_id) == (&b.name, &b.public_id, &b.system_id)
+        }
+        (NodeData::Text(_), NodeData::Text(_))
+        | (NodeData::Comment(_), NodeData::Comment(_))
+        | (NodeData::Document(_), NodeData::Document(_))
+        | (NodeData::DocumentFragment, NodeData::DocumentFragment) => true,
+        _ => false,
+    }
+}
+
+/// The name of a node in a path, in the style of XPath.
+fn step_name(node: &NodeRef) -> Cow<'static, str> {
+    match node.data() {
+        NodeData::Element(elem) => {
+            let name = elem.name.borrow();
+            match &name.prefix {
+                Some(prefix) => Cow::Owned(format!("{prefix}:{}", name.local)),
+                None => Cow::Owned(name.local.to_string()),
+            }
+        }
+        NodeData::Text(_) => Cow::Borrowed("text()"),
+        NodeData::Comment(_) => Cow::Borrowed("comment()"),
+        NodeData::ProcessingInstruction(_) => Cow::Borrowed("processing-instruction()"),
+        NodeData::Doctype(_) => Cow::Borrowed("doctype()"),
+        NodeData::Document(_) | NodeData::DocumentFragment => Cow::Borrowed("node()"),
+    }
+}
+
+/// Returns a path such as `/html[1]/body[1]/p[2]/text()[1]`, where each step is counted among the siblings with
+/// the same name.
+fn node_path(node: &NodeRef, root: &NodeRef) -> String {
+    let mut steps = Vec::new();
+    let mut node = node.clone();
+    while node != *root {
+        let Some(parent) = node.parent() else {
+            break;
+        };
+        let name = step_name(&node);
+        let index = node
+            .preceding_siblings()
+            .filter(|x| step_name(x) == name)
+            .count()
+            + 1;
+        steps.push(format!("{name}[{index}]"));
+        node = parent;
+    }
+    steps.reverse();
+    format!("/{}", steps.join("/"))
+}
+
+fn attribute_names(node: &NodeRef) -> Vec<String> {
+    let Some(elem) = node.as_element() else {
+        return Vec::new();
+    };
+    let attrs = elem.attributes.borrow();
+    attrs
+        .map
+        .iter()
+        .map(|(name, attr)| match &attr.prefix {
+            Some(prefix) => format!("{prefix}:{}", name.local),
+            None => name.local.to_string(),
+        })
+        .collect()
+}
+
+/// Finds a longest common subsequence of two lists, returning the indices of the matched items.
+///
+/// Common prefixes and suffixes are matched first, so that the quadratic part of the search is limited to the
+/// part of the lists that actually changed.
+fn lcs<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| eq(x, y)).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| eq(x, y))
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    // table[i][j] is the length of the longest common subsequence of a_mid[i..] and b_mid[j..]
+    let width = b_mid.len() + 1;
+    let mut table = vec![0u32; (a_mid.len() + 1) * width];
+    for i in (0..a_mid.len()).rev() {
+        for j in (0..b_mid.len()).rev() {
+            table[i * width + j] = if eq(&a_mid[i], &b_mid[j]) {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut result: Vec<_> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a_mid.len() && j < b_mid.len() {
+        if eq(&a_mid[i], &b_mid[j]) {
+            result.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    for k in 0..suffix {
+        result.push((a.len() - suffix + k, b.len() - suffix + k));
+    }
+    result
+}
+
+struct Differ<'a> {
+    options: &'a DiffOptions,
+    old_root: &'a NodeRef,
+    new_root: &'a NodeRef,
+    hashes: WyHashMap<usize, u64>,
+    old_hashes: WyHashSet<u64>,
+    new_hashes: WyHashSet<u64>,
+    changes: Vec<Option<Change>>,
+    removed: WyHashMap<u64, VecDeque<usize>>,
+    inserted: Vec<(u64, usize)>,
+}
+impl Differ<'_> {
+    fn children(&self, node: &NodeRef) -> Vec<NodeRef> {
+        node.children()
+            .filter(|x| !(self.options.ignore_whitespace && is_whitespace(x)))
+            .collect()
+    }
+
+    /// Hashes the contents of every node in a tree, so that equal subtrees can be found quickly.
+    fn hash_tree(&mut self, node: &NodeRef) -> u64 {
+        let mut hasher = WyHashBuilder.build_hasher();
+        std::mem::discriminant(node.data()).hash(&mut hasher);
+        match node.data() {
+            NodeData::Element(elem) => {
+                elem.name.borrow().hash(&mut hasher);
+                let attrs = elem.attributes.borrow();
+                let mut list: Vec<_> = attrs.map.iter().map(|(k, v)| (k, &v.value)).collect();
+                if self.options.ignore_attribute_order {
+                    list.sort();
+                }
+                list.hash(&mut hasher);
+            }
+            NodeData::Text(text) | NodeData::Comment(text) => text.borrow().hash(&mut hasher),
+            NodeData::ProcessingInstruction(pi) => pi.borrow().hash(&mut hasher),
+            NodeData::Doctype(doctype) => {
+                (&doctype.name, &doctype.public_id, &doctype.system_id).hash(&mut hasher)
+            }
+            NodeData::Document(_) | NodeData::DocumentFragment => {}
+        }
+        for child in self.children(node) {
+            self.hash_tree(&child).hash(&mut hasher);
+        }
+        let hash = hasher.finish();
+        self.hashes.insert(node_id(node), hash);
+        hash
+    }
+
+    fn tree_hashes(&self, root: &NodeRef) -> WyHashSet<u64> {
+        root.inclusive_descendants()
+            .filter_map(|x| self.hashes.get(&node_id(&x)).copied())
+            .collect()
+    }
+
+    fn hash(&self, node: &NodeRef) -> u64 {
+        self.hashes[&node_id(node)]
+    }
+
+    fn push(&mut self, change: Change) {
+        self.changes.push(Some(change));
+    }
+
+    fn remove(&mut self, node: &NodeRef) {
+        let path = node_path(node, self.old_root);
+        let index = self.changes.len();
+        self.push(Change::Remove { path, node: node.clone() });
+        let hash = self.hash(node);
+        self.removed.entry(hash).or_default().push_back(index);
+    }
+
+    fn insert(&mut self, node: &NodeRef) {
+        let path = node_path(node, self.new_root);
+        let index = self.changes.len();
+        self.push(Change::Insert { path, node: node.clone() });
+        self.inserted.push((self.hash(node), index));
+    }
+
+    fn diff_attributes(&mut self, old: &NodeRef, new: &NodeRef) {
+        let (Some(old_elem), Some(new_elem)) = (old.as_element(), new.as_element()) else {
+            return;
+        };
+        let path = node_path(new, self.new_root);
+        let old_attrs = old_elem.attributes.borrow();
+        let new_attrs = new_elem.attributes.borrow();
+        let old_names = attribute_names(old);
+        let new_names = attribute_names(new);
+
+        for ((key, attr), name) in old_attrs.map.iter().zip(&old_names) {
+            match new_attrs.map.get(key) {
+                Some(new_attr) if new_attr.value == attr.value => {}
+                new_attr => self.push(Change::Attribute {
+                    path: path.clone(),
+                    name: name.clone(),
+                    old: Some(attr.value.clone()),
+                    new: new_attr.map(|x| x.value.clone()),
+                }),
+            }
+        }
+        for ((key, attr), name) in new_attrs.map.iter().zip(&new_names) {
+            if !old_attrs.map.contains_key(key) {
+                self.push(Change::Attribute {
+                    path: path.clone(),
+                    name: name.clone(),
+                    old: None,
+                    new: Some(attr.value.clone()),
+                });
+            }
+        }
+
+        if !self.options.ignore_attribute_order {
+            // Only compare the order of the attributes both elements have, as the others were already reported
+            let old_order: Vec<_> = old_attrs
+                .map
+                .keys()
+                .filter(|x| new_attrs.map.contains_key(*x))
+                .collect();
+            let new_order: Vec<_> = new_attrs
+                .map
+                .keys()
+                .filter(|x| old_attrs.map.contains_key(*x))
+                .collect();
+            if old_order != new_order {
+                self.push(Change::AttributeOrder { path, old: old_names, new: new_names });
+            }
+        }
+    }
+
+    fn diff_node(&mut self, old: &NodeRef, new: &NodeRef) {
+        if self.hash(old) == self.hash(new) {
+            return;
+        }
+        match (old.data(), new.data()) {
+            (NodeData::Text(old_text), NodeData::Text(new_text))
+            | (NodeData::Comment(old_text), NodeData::Comment(new_text)) => {
+                self.push(Change::Text {
+                    path: node_path(new, self.new_root),
+                    old: old_text.borrow().clone(),
+                    new: new_text.borrow().clone(),
+                });
+            }
+            (NodeData::ProcessingInstruction(old_pi), NodeData::ProcessingInstruction(new_pi)) => {
+                self.push(Change::Text {
+                    path: node_path(new, self.new_root),
+                    old: old_pi.borrow().1.clone(),
+                    new: new_pi.borrow().1.clone(),
+                });
+            }
+            _ => {
+                self.diff_attributes(old, new);
+                self.diff_children(old, new);
+            }
+        }
+    }
+
+    fn diff_children(&mut self, old_parent: &NodeRef, new_parent: &NodeRef) {
+        let old = self.children(old_parent);
+        let new = self.children(new_parent);
+
+        // Children that are unchanged are found first, and the children between them are then matched up by
+        // their kind, so that changes inside of them are found.
+        let old_hashes: Vec<_> = old.iter().map(|x| self.hash(x)).collect();
+        let new_hashes: Vec<_> = new.iter().map(|x| self.hash(x)).collect();
+        let unchanged = lcs(&old_hashes, &new_hashes, |a, b| a == b);
+
+        let (mut old_start, mut new_start) = (0, 0);
+        for (old_end, new_end) in unchanged.into_iter().chain([(old.len(), new.len())]) {
+            self.diff_range(&old[old_start..old_end], &new[new_start..new_end]);
+            (old_start, new_start) = (old_end + 1, new_end + 1);
+        }
+    }
+
+    fn diff_range(&mut self, old: &[NodeRef], new: &[NodeRef]) {
+        // Nodes that appear unchanged somewhere in the other tree were most likely moved, so they aren't matched
+        // with a different node of the same kind here
+        let old_candidates: Vec<_> = (0..old.len())
+            .filter(|i| !self.new_hashes.contains(&self.hash(&old[*i])))
+            .collect();
+        let new_candidates: Vec<_> = (0..new.len())
+            .filter(|i| !self.old_hashes.contains(&self.hash(&new[*i])))
+            .collect();
+        let matched =
+            lcs(&old_candidates, &new_candidates, |a, b| is_same_kind(&old[*a], &new[*b]));
+
+        let (mut old_start, mut new_start) = (0, 0);
+        for (old_end, new_end) in matched
+            .into_iter()
+            .map(|(a, b)| (old_candidates[a], new_candidates[b]))
+            .chain([(old.len(), new.len())])
+        {
+            for node in &old[old_start..old_end] {
+                self.remove(node);
+            }
+            for node in &new[new_start..new_end] {
+                self.insert(node);
+            }
+            if old_end < old.len() {
+                self.diff_node(&old[old_end], &new[new_end]);
+            }
+            (old_start, new_start) = (old_end + 1, new_end + 1);
+        }
+    }
+
+    /// Turns pairs of a removed and an inserted node with the same contents into moves.
+    fn find_moves(&mut self) {
+        for (hash, index) in std::mem::take(&mut self.inserted) {
+            let Some(removed) = self.removed.get_mut(&hash).and_then(|x| x.pop_front()) else {
+                continue;
+            };
+            let Some(Change::Remove { path: from, .. }) = self.changes[removed].take() else {
+                unreachable!()
+            };
+            if let Some(Change::Insert { path, node }) = self.changes[index].take() {
+                self.changes[index] = Some(Change::Move { from, path, node });
+            }
+        }
+    }
+}
+
+/// Compares two trees, and returns the changes that turn the first one into the second one.
+///
+/// Children are compared with a longest common subsequence search, first for unchanged children and then for
+/// children of the same kind (such as elements with the same name) among the rest, which are compared recursively.
+/// Subtrees that were removed in one place and inserted unchanged in another are reported as moves.
+pub fn diff(old: &NodeRef, new: &NodeRef, options: &DiffOptions) -> Vec<Change> {
+    let mut differ = Differ {
+        options,
+        old_root: old,
+        new_root: new,
+        hashes: WyHashMap::default(),
+        old_hashes: WyHashSet::default(),
+        new_hashes: WyHashSet::default(),
+        changes: Vec::new(),
+        removed: WyHashMap::default(),
+        inserted: Vec::new(),
+    };
+    differ.hash_tree(old);
+    differ.hash_tree(new);
+    differ.old_hashes = differ.tree_hashes(old);
+    differ.new_hashes = differ.tree_hashes(new);
+
+    if is_same_kind(old, new) {
+        differ.diff_node(old, new);
+    } else {
+        differ.remove(old);
+        differ.insert(new);
+    }
+    differ.find_moves();
+    differ.changes.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kuchikiki::traits::TendrilSink;
+
+    fn describe(change: &Change) -> String {
+        match change {
+            Change::Insert { path, .. } => format!("insert {path}"),
+            Change::Remove { path, .. } => format!("remove {path}"),
+            Change::Move { from, path, .. } => format!("move {from} -> {path}"),
+            Change::Attribute { path, name, old, new } => {
+                format!("attribute {path} {name} {old:?} -> {new:?}")
+            }
+            Change::AttributeOrder { path, old, new } => {
+                format!("order {path} {old:?} -> {new:?}")
+            }
+            Change::Text { path, old, new } => format!("text {path} {old:?} -> {new:?}"),
+        }
+    }
+
+    fn changes(old: &str, new: &str, options: &DiffOptions) -> Vec<String> {
+        let old = kuchikiki::parse_html().one(old);
+        let new = kuchikiki::parse_html().one(new);
+        diff(&old, &new, options).iter().map(describe).collect()
+    }
+
+    #[test]
+    fn lcs_keeps_common_prefix_and_suffix() {
+        assert_eq!(lcs(&[1, 2, 3], &[1, 2, 3], |a, b| a == b), [(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(lcs(&[1, 2, 3, 4], &[1, 5, 3, 4], |a, b| a == b), [(0, 0), (2, 2), (3, 3)]);
+        assert_eq!(lcs(&[1, 2, 3, 4, 5], &[3, 4, 1, 2, 5], |a, b| a == b).len(), 3);
+        assert_eq!(lcs::<i32>(&[], &[1], |a, b| a == b), []);
+    }
+
+    #[test]
+    fn node_paths_count_siblings_with_the_same_name() {
+        let document = kuchikiki::parse_html().one("<p>a</p><div></div><p>b<!--c--><em>d</em></p>");
+        let em = document.select_first("em").unwrap().as_node().clone();
+        assert_eq!(node_path(&em, &document), "/html[1]/body[1]/p[2]/em[1]");
+        let text = em.first_child().unwrap();
+        assert_eq!(node_path(&text, &document), "/html[1]/body[1]/p[2]/em[1]/text()[1]");
+        let comment = em.previous_sibling().unwrap();
+        assert_eq!(node_path(&comment, &document), "/html[1]/body[1]/p[2]/comment()[1]");
+        assert_eq!(node_path(&document, &document), "/");
+    }
+
+    #[test]
+    fn identical_trees_have_no_changes() {
+        let html = "<p class=a>x</p><ul><li>1</li><li>2</li></ul>";
+        assert!(changes(html, html, &DiffOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn text_and_attribute_changes() {
+        assert_eq!(
+            changes("<p class=a>x</p>", "<p class=b id=c>y</p>", &DiffOptions::default()),
+            [
+                "attribute /html[1]/body[1]/p[1] class Some(\"a\") -> Some(\"b\")",
+                "attribute /html[1]/body[1]/p[1] id None -> Some(\"c\")",
+                "text /html[1]/body[1]/p[1]/text()[1] \"x\" -> \"y\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn inserted_and_removed_children() {
+        assert_eq!(
+            changes("<p>a</p><p>b</p>", "<p>a</p><div></div><p>b</p>", &DiffOptions::default()),
+            ["insert /html[1]/body[1]/div[1]"]
+        );
+        assert_eq!(
+            changes("<p>a</p><hr><p>b</p>", "<p>a</p><p>b</p>", &DiffOptions::default()),
+            ["remove /html[1]/body[1]/hr[1]"]
+        );
+    }
+
+    #[test]
+    fn moved_subtrees() {
+        assert_eq!(
+            changes(
+                "<ul><li>1</li><li>2</li><li>3</li></ul>",
+                "<ul><li>3</li><li>1</li><li>2</li></ul>",
+                &DiffOptions::default()
+            ),
+            ["move /html[1]/body[1]/ul[1]/li[3] -> /html[1]/body[1]/ul[1]/li[1]"]
+        );
+    }
+
+    #[test]
+    fn attribute_order_and_whitespace_options() {
+        let old = "<p a=1 b=2>x</p> <p>y</p>";
+        let new = "<p b=2 a=1>x</p><p>y</p>";
+        assert_eq!(
+            changes(old, new, &DiffOptions::default()),
+            [
+                "order /html[1]/body[1]/p[1] [\"a\", \"b\"] -> [\"b\", \"a\"]",
+                "remove /html[1]/body[1]/text()[1]",
+            ]
+        );
+        let options = DiffOptions { ignore_whitespace: true, ignore_attribute_order: true };
+        assert!(changes(old, new, &options).is_empty());
+    }
+}
@@ -79,7 +79,8 @@ impl TreeSink for IsDocumentTreeSink {
     }
 
     fn get_template_contents(&mut self, _: &Self::Handle) -> Self::Handle {
-        todo!()
+        self.elements.push(None);
+        self.handle()
     }
 
     fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
@@ -0,0 +1,245 @@
+use crate::wyhash::WyHashSet;
+use pulldown_cmark::{
+    html::{push_html, write_html_fmt},
+    CowStr, Event, Options, Parser, Tag, TagEnd,
+};
+use std::{cell::RefCell, fmt, ops::Range, rc::Rc};
+
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_HEADING_ATTRIBUTES
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
+        | Options::ENABLE_GFM
+}
+
+fn heading_slug(text: &str) -> String {
+    let mut slug = String::new();
+    for ch in text.trim().chars() {
+        if ch.is_alphanumeric() || ch == '_' || ch == '-' {
+            slug.extend(ch.to_lowercase());
+        } else if ch.is_whitespace() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Assigns GitHub-style IDs to all headings that do not already have an explicit `{#id}`.
+fn assign_heading_ids(events: &mut [Event]) {
+    let mut used = WyHashSet::default();
+    for event in events.iter() {
+        if let Event::Start(Tag::Heading { id: Some(id), .. }) = event {
+            used.insert(id.to_string());
+        }
+    }
+
+    let mut i = 0;
+    while i < events.len() {
+        if let Event::Start(Tag::Heading { id: None, .. }) = &events[i] {
+            let mut text = String::new();
+            for event in &events[i + 1..] {
+                match event {
+                    Event::End(TagEnd::Heading(_)) => break,
+                    Event::Text(str) | Event::Code(str) => text.push_str(str),
+                    _ => {}
+                }
+            }
+
+            let base = heading_slug(&text);
+            let mut slug = base.clone();
+            let mut count = 1;
+            while used.contains(&slug) {
+                slug = format!("{base}-{count}");
+                count += 1;
+            }
+            used.insert(slug.clone());
+
+            if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+                *id = Some(CowStr::from(slug));
+            }
+        }
+        i += 1;
+    }
+}
+
+/// Parses Markdown into events and their ranges in the source, skipping front matter blocks (`---` or `+++`
+/// delimited), as they are handled separately.
+fn markdown_events(source: &str) -> (Vec<Event<'_>>, Vec<Range<usize>>) {
+    let mut in_metadata = false;
+    let (mut events, ranges): (Vec<_>, Vec<_>) = Parser::new_ext(source, markdown_options())
+        .into_offset_iter()
+        .filter(|(event, _)| match event {
+            Event::Start(Tag::MetadataBlock(_)) => {
+                in_metadata = true;
+                false
+            }
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                in_metadata = false;
+                false
+            }
+            _ => !in_metadata,
+        })
+        .unzip();
+    assign_heading_ids(&mut events);
+    (events, ranges)
+}
+
+/// Renders CommonMark (with GitHub extensions) to HTML.
+///
+/// Front matter blocks (`---` or `+++` delimited) are skipped, as they are handled separately.
+pub fn markdown_to_html(source: &str) -> String {
+    let (events, _) = markdown_events(source);
+    let mut html = String::new();
+    push_html(&mut html, events.into_iter());
+    html
+}
+
+/// Finds the byte offset of a 1-based line and column (counted in characters) in a text.
+fn offset_of(text: &str, line: u64, column: u64) -> usize {
+    let line_start = match line {
+        0 | 1 => 0,
+        _ => text
+            .match_indices('\n')
+            .nth(line as usize - 2)
+            .map_or(text.len(), |(i, _)| i + 1),
+    };
+    let rest = &text[line_start..];
+    line_start
+        + rest
+            .char_indices()
+            .nth(column.saturating_sub(1) as usize)
+            .map_or(rest.len(), |(i, _)| i)
+}
+
+/// Finds the 1-based line and column (counted in characters) of a byte offset in a text.
+fn position_of(text: &str, offset: usize) -> (u64, u64) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() + 1;
+    (line as u64, before[line_start..].chars().count() as u64 + 1)
+}
+
+/// Maps locations in the HTML rendered by [`markdown_to_html_with_source_map`] back to the Markdown source.
+pub struct MarkdownSourceMap<'a> {
+    source: &'a str,
+    /// For each event, where its output starts in the HTML, and its range in the source if it is raw HTML that is
+    /// copied to the output as is, or just its start otherwise.
+    events: Vec<(usize, Range<usize>, bool)>,
+}
+impl MarkdownSourceMap<'_> {
+    /// Converts a line and column in the rendered HTML into the line and column of the Markdown it came from.
+    pub fn locate(&self, html: &str, line: u64, column: u64) -> (u64, u64) {
+        let html_offset = offset_of(html, line, column);
+        let index = self.events.partition_point(|(x, _, _)| *x <= html_offset);
+        let Some((event_offset, range, is_raw)) = index.checked_sub(1).map(|i| &self.events[i])
+        else {
+            return (1, 1);
+        };
+        let offset = if *is_raw {
+            // raw HTML is copied as is, so the element is at the same place within it
+            range.start + (html_offset - event_offset).min(range.len())
+        } else {
+            range.start
+        };
+        position_of(self.source, offset)
+    }
+}
+
+struct SharedWriter(Rc<RefCell<String>>);
+impl fmt::Write for SharedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.borrow_mut().push_str(s);
+        Ok(())
+    }
+}
+
+/// Renders Markdown like [`markdown_to_html`], and also returns where the output of each part of the source starts,
+/// so that the locations of elements can be traced back to the Markdown.
+pub fn markdown_to_html_with_source_map(source: &str) -> (String, MarkdownSourceMap<'_>) {
+    let (events, ranges) = markdown_events(source);
+    let html = Rc::new(RefCell::new(String::new()));
+    let mut map = Vec::new();
+
+    // The HTML writer pulls each event after it has written the previous one, so the length of the output at that
+    // point is where the event's output starts.
+    let iter = events.into_iter().zip(ranges).map(|(event, range)| {
+        let is_raw = matches!(event, Event::Html(_) | Event::InlineHtml(_));
+        map.push((html.borrow().len(), range, is_raw));
+        event
+    });
+    write_html_fmt(SharedWriter(html.clone()), iter).expect("writing to a string can't fail");
+
+    let html = html.take();
+    (html, MarkdownSourceMap { source, events: map })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_get_unique_ids() {
+        let html = markdown_to_html("# Hello World\n\n# Hello World\n\n# Custom {#x}\n");
+        assert_eq!(
+            html,
+            "<h1 id=\"hello-world\">Hello World</h1>\n<h1 id=\"hello-world-1\">Hello World</h1>\n\
+             <h1 id=\"x\">Custom</h1>\n"
+        );
+    }
+
+    #[test]
+    fn front_matter_is_skipped() {
+        assert_eq!(markdown_to_html("---\ntitle: x\n---\ntext\n"), "<p>text</p>\n");
+    }
+
+    #[test]
+    fn offsets_and_positions() {
+        let text = "ab\nçd\n";
+        assert_eq!(offset_of(text, 2, 2), 5);
+        assert_eq!(position_of(text, 5), (2, 2));
+        assert_eq!(position_of(text, 100), (3, 1));
+    }
+
+    #[test]
+    fn source_map_locates_elements() {
+        let source = "# Title\n\nSome *text*.\n\n<div>\n  <span>raw</span>\n</div>\n";
+        let (html, map) = markdown_to_html_with_source_map(source);
+        let locate = |needle: &str| {
+            let offset = html.find(needle).unwrap();
+            let (line, column) = position_of(&html, offset);
+            map.locate(&html, line, column)
+        };
+        assert_eq!(locate("<h1"), (1, 1));
+        assert_eq!(locate("<p>"), (3, 1));
+        assert_eq!(locate("<em>"), (3, 6));
+        assert_eq!(locate("<div>"), (5, 1));
+        assert_eq!(locate("<span>"), (6, 3));
+    }
+
+    #[test]
+    fn source_map_counts_front_matter_lines() {
+        // pages.luau replaces the front matter with blank lines, and pulldown-cmark skips it when it is still there
+        for source in ["---\ntitle: x\n---\n# Title\n\n*a*\n", "\n\n\n# Title\n\n*a*\n"] {
+            let (html, map) = markdown_to_html_with_source_map(source);
+            let locate = |needle: &str| {
+                let (line, column) = position_of(&html, html.find(needle).unwrap());
+                map.locate(&html, line, column)
+            };
+            assert_eq!(locate("<h1"), (4, 1));
+            assert_eq!(locate("<em>"), (6, 1));
+        }
+    }
+}
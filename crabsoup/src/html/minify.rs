@@ -0,0 +1,802 @@
+use html5ever::{namespace_url, ns, LocalName};
+use kuchikiki::{ElementData, NodeData, NodeRef};
+
+fn is_html(elem: &ElementData) -> bool {
+    elem.name.borrow().ns == ns!(html)
+}
+fn html_name(node: &NodeRef) -> Option<LocalName> {
+    let elem = node.as_element()?;
+    is_html(elem).then(|| elem.name.borrow().local.clone())
+}
+
+pub(crate) fn is_void(name: &str) -> bool {
+    matches!(
+        name,
+        "area"
+            | "base"
+            | "basefont"
+            | "bgsound"
+            | "br"
+            | "col"
+            | "embed"
+            | "frame"
+            | "hr"
+            | "img"
+            | "input"
+            | "keygen"
+            | "link"
+            | "meta"
+            | "param"
+            | "source"
+            | "track"
+            | "wbr"
+    )
+}
+pub(crate) fn is_raw_text(name: &str) -> bool {
+    matches!(
+        name,
+        "style" | "script" | "xmp" | "iframe" | "noembed" | "noframes" | "plaintext" | "noscript"
+    )
+}
+pub(crate) fn is_whitespace_sensitive(name: &str) -> bool {
+    matches!(name, "pre" | "textarea" | "listing" | "plaintext")
+}
+pub(crate) fn is_inline(name: &str) -> bool {
+    matches!(
+        name,
+        "a" | "abbr"
+            | "audio"
+            | "b"
+            | "bdi"
+            | "bdo"
+            | "big"
+            | "br"
+            | "button"
+            | "canvas"
+            | "cite"
+            | "code"
+            | "data"
+            | "del"
+            | "dfn"
+            | "em"
+            | "font"
+            | "i"
+            | "iframe"
+            | "img"
+            | "input"
+            | "ins"
+            | "kbd"
+            | "label"
+            | "map"
+            | "mark"
+            | "math"
+            | "meter"
+            | "nobr"
+            | "object"
+            | "output"
+            | "picture"
+            | "progress"
+            | "q"
+            | "ruby"
+            | "s"
+            | "samp"
+            | "select"
+            | "small"
+            | "span"
+            | "strike"
+            | "strong"
+            | "sub"
+            | "sup"
+            | "svg"
+            | "textarea"
+            | "time"
+            | "tt"
+            | "u"
+            | "var"
+            | "video"
+            | "wbr"
+    ) || name.contains('-')
+}
+
+/// Whether an element's surrounding whitespace can be removed without changing how the page renders.
+fn is_block(node: &NodeRef) -> bool {
+    match node.as_element() {
+        Some(elem) if is_html(elem) => !is_inline(&elem.name.borrow().local),
+        _ => false,
+    }
+}
+
+fn is_kept_comment(text: &str) -> bool {
+    text.starts_with("[if") || text.starts_with("<![endif]")
+}
+fn is_dropped(node: &NodeRef) -> bool {
+    match node.data() {
+        NodeData::Comment(text) => !is_kept_comment(&text.borrow()),
+        _ => false,
+    }
+}
+fn is_blank_text(node: &NodeRef) -> bool {
+    match node.as_text() {
+        Some(text) => text.borrow().chars().all(|x| x.is_ascii_whitespace()),
+        None => false,
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Context {
+    preserve_whitespace: bool,
+    foreign: bool,
+}
+
+struct Minifier {
+    out: String,
+}
+impl Minifier {
+    fn push_escaped(&mut self, text: &str, attribute: bool) {
+        for ch in text.chars() {
+            match ch {
+                '&' => self.out.push_str("&amp;"),
+                '\u{a0}' => self.out.push_str("&nbsp;"),
+                '"' if attribute => self.out.push_str("&quot;"),
+                '<' if !attribute => self.out.push_str("&lt;"),
+                '>' if !attribute => self.out.push_str("&gt;"),
+                _ => self.out.push(ch),
+            }
+        }
+    }
+
+    fn previous_sibling(node: &NodeRef) -> Option<NodeRef> {
+        let mut current = node.previous_sibling();
+        while let Some(sibling) = current {
+            if !is_dropped(&sibling) {
+                return Some(sibling);
+            }
+            current = sibling.previous_sibling();
+        }
+        None
+    }
+    fn next_sibling(node: &NodeRef, ctx: Context, skip_blank: bool) -> Option<NodeRef> {
+        let mut current = node.next_sibling();
+        while let Some(sibling) = current {
+            let blank = skip_blank && !ctx.preserve_whitespace && !ctx.foreign;
+            if !(is_dropped(&sibling) || blank && is_blank_text(&sibling)) {
+                return Some(sibling);
+            }
+            current = sibling.next_sibling();
+        }
+        None
+    }
+
+    fn text(&mut self, node: &NodeRef, text: &str, ctx: Context) {
+        if ctx.preserve_whitespace {
+            self.push_escaped(text, false);
+            return;
+        }
+
+        let mut collapsed = String::with_capacity(text.len());
+        for ch in text.chars() {
+            if ch.is_ascii_whitespace() {
+                if !collapsed.ends_with(' ') {
+                    collapsed.push(' ');
+                }
+            } else {
+                collapsed.push(ch);
+            }
+        }
+
+        let mut collapsed = collapsed.as_str();
+        if !ctx.foreign {
+            let parent_is_block = match node.parent() {
+                Some(parent) => parent.as_element().is_none() || is_block(&parent),
+                None => true,
+            };
+            let trim_start = match Self::previous_sibling(node) {
+                Some(prev) => is_block(&prev),
+                None => parent_is_block,
+            } || self.out.ends_with(' ');
+            let trim_end = match Self::next_sibling(node, ctx, false) {
+                Some(next) => is_block(&next),
+                None => parent_is_block,
+            };
+
+            if trim_start {
+                collapsed = collapsed.trim_start_matches(' ');
+            }
+            if trim_end {
+                collapsed = collapsed.trim_end_matches(' ');
+            }
+        }
+        self.push_escaped(collapsed, false);
+    }
+
+    fn raw_text(&mut self, parent: &NodeRef, name: &str, text: &str) {
+        let elem = parent.as_element().unwrap();
+        let attrs = elem.attributes.borrow();
+        let ty = attrs
+            .get("type")
+            .map(|x| x.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        match name {
+            "style" if ty.is_empty() || ty == "text/css" => self.out.push_str(&minify_css(text)),
+            "script" if is_script_type(&ty) => self.out.push_str(&minify_js(text)),
+            _ => self.out.push_str(text),
+        }
+    }
+
+    fn end_tag_optional(&self, node: &NodeRef, name: &str, ctx: Context) -> bool {
+        let next = Self::next_sibling(node, ctx, true);
+        let next_name = next.as_ref().and_then(html_name);
+        let next_is = |names: &[&str]| match &next_name {
+            Some(next) => names.contains(&&**next),
+            None => false,
+        };
+        let at_end = next.is_none();
+
+        match name {
+            "li" => at_end || next_is(&["li"]),
+            "dt" => next_is(&["dt", "dd"]),
+            "dd" => at_end || next_is(&["dt", "dd"]),
+            "rt" | "rp" => at_end || next_is(&["rt", "rp"]),
+            "optgroup" => at_end || next_is(&["optgroup"]),
+            "option" => at_end || next_is(&["option", "optgroup"]),
+            "thead" => next_is(&["tbody", "tfoot"]),
+            "tbody" => at_end || next_is(&["tbody", "tfoot"]),
+            "tfoot" => at_end,
+            "tr" => at_end || next_is(&["tr"]),
+            "td" | "th" => at_end || next_is(&["td", "th"]),
+            "head" | "body" | "html" => {
+                !matches!(next.as_ref().map(|x| x.data()), Some(NodeData::Comment(_)))
+            }
+            "p" => {
+                if at_end {
+                    match node.parent().as_ref().and_then(html_name) {
+                        Some(parent) => {
+                            !matches!(
+                                &*parent,
+                                "a" | "audio" | "del" | "ins" | "map" | "noscript" | "video"
+                            ) && !parent.contains('-')
+                        }
+                        None => false,
+                    }
+                } else {
+                    next_is(&[
+                        "address",
+                        "article",
+                        "aside",
+                        "blockquote",
+                        "details",
+                        "div",
+                        "dl",
+                        "fieldset",
+                        "figcaption",
+                        "figure",
+                        "footer",
+                        "form",
+                        "h1",
+                        "h2",
+                        "h3",
+                        "h4",
+                        "h5",
+                        "h6",
+                        "header",
+                        "hgroup",
+                        "hr",
+                        "main",
+                        "menu",
+                        "nav",
+                        "ol",
+                        "p",
+                        "pre",
+                        "section",
+                        "table",
+                        "ul",
+                    ])
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn element(&mut self, node: &NodeRef, elem: &ElementData, ctx: Context) {
+        let name = elem.name.borrow().local.clone();
+        let html = is_html(elem);
+
+        self.out.push('<');
+        self.out.push_str(&name);
+        for (attr_name, attr) in elem.attributes.borrow().map.iter() {
+            self.out.push(' ');
+            if attr_name.ns == ns!(xml) {
+                self.out.push_str("xml:");
+            } else if attr_name.ns == ns!(xmlns) && &*attr_name.local != "xmlns" {
+                self.out.push_str("xmlns:");
+            } else if attr_name.ns == ns!(xlink) {
+                self.out.push_str("xlink:");
+            } else if let Some(prefix) = &attr.prefix {
+                self.out.push_str(prefix);
+                self.out.push(':');
+            }
+            self.out.push_str(&attr_name.local);
+
+            let value = attr.value.as_str();
+            if value.is_empty() {
+                // `<input disabled>` is equivalent to `<input disabled="">`
+            } else if !value.contains(|x: char| " \t\n\r\x0C\"'=<>`".contains(x)) {
+                self.out.push('=');
+                self.push_escaped(value, true);
+            } else {
+                self.out.push_str("=\"");
+                self.push_escaped(value, true);
+                self.out.push('"');
+            }
+        }
+        self.out.push('>');
+
+        if html && is_void(&name) {
+            return;
+        }
+
+        let child_ctx = Context {
+            preserve_whitespace: ctx.preserve_whitespace
+                || (html && is_whitespace_sensitive(&name)),
+            foreign: ctx.foreign || !html,
+        };
+        let children = match &elem.template_contents {
+            Some(contents) => contents.clone(),
+            None => node.clone(),
+        };
+        if html && is_raw_text(&name) {
+            let mut text = String::new();
+            for child in children.children() {
+                if let Some(child_text) = child.as_text() {
+                    text.push_str(&child_text.borrow());
+                }
+            }
+            self.raw_text(node, &name, &text);
+        } else {
+            if html && matches!(&*name, "pre" | "textarea" | "listing") {
+                // the parser ignores a newline directly after these tags, so it must be doubled
+                if let Some(text) = children.first_child().as_ref().and_then(|x| x.as_text()) {
+                    if text.borrow().starts_with('\n') {
+                        self.out.push('\n');
+                    }
+                }
+            }
+            for child in children.children() {
+                self.node(&child, child_ctx);
+            }
+        }
+
+        if !(html && !ctx.preserve_whitespace && self.end_tag_optional(node, &name, ctx)) {
+            self.out.push_str("</");
+            self.out.push_str(&name);
+            self.out.push('>');
+        }
+    }
+
+    fn node(&mut self, node: &NodeRef, ctx: Context) {
+        match node.data() {
+            NodeData::Element(elem) => self.element(node, elem, ctx),
+            NodeData::Text(text) => self.text(node, &text.borrow(), ctx),
+            NodeData::Comment(text) => {
+                let text = text.borrow();
+                if is_kept_comment(&text) {
+                    self.out.push_str("<!--");
+                    self.out.push_str(&text);
+                    self.out.push_str("-->");
+                }
+            }
+            NodeData::ProcessingInstruction(contents) => {
+                let contents = contents.borrow();
+                self.out.push_str("<?");
+                self.out.push_str(&contents.0);
+                self.out.push(' ');
+                self.out.push_str(&contents.1);
+                self.out.push('>');
+            }
+            NodeData::Doctype(doctype) => {
+                self.out.push_str("<!doctype ");
+                self.out.push_str(&doctype.name);
+                self.out.push('>');
+            }
+            NodeData::Document(_) | NodeData::DocumentFragment => {
+                for child in node.children() {
+                    self.node(&child, ctx);
+                }
+            }
+        }
+    }
+}
+
+/// Serializes a node as HTML, removing insignificant whitespace, comments, optional end tags and
+/// attribute quotes, and minifying the contents of `<style>` and `<script>` tags.
+///
+/// Whitespace is preserved inside `<pre>`, `<textarea>` and foreign (SVG or MathML) content.
+pub fn minify(node: &NodeRef) -> String {
+    let mut minifier = Minifier { out: String::new() };
+    let ctx = Context::default();
+    match node.data() {
+        NodeData::Text(text) => minifier.text(node, &text.borrow(), ctx),
+        _ => minifier.node(node, ctx),
+    }
+    minifier.out
+}
+
+fn is_script_type(ty: &str) -> bool {
+    matches!(
+        ty,
+        "" | "module"
+            | "text/javascript"
+            | "application/javascript"
+            | "text/ecmascript"
+            | "application/ecmascript"
+            | "application/json"
+            | "application/ld+json"
+            | "importmap"
+            | "speculationrules"
+    )
+}
+
+/// Minifies CSS by removing comments and unnecessary whitespace.
+pub fn minify_css(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(ch) = chars.next() {
+        if ch.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if ch == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            let mut last = '\0';
+            for ch in chars.by_ref() {
+                if last == '*' && ch == '/' {
+                    break;
+                }
+                last = ch;
+            }
+            pending_space = true;
+            continue;
+        }
+
+        if pending_space {
+            pending_space = false;
+            let prev = out.chars().last();
+            let removable_after = prev.is_none_or(|x| "{};,>:(".contains(x));
+            let removable_before = "{};,>!)".contains(ch);
+            if !removable_after && !removable_before {
+                out.push(' ');
+            }
+        }
+
+        match ch {
+            '"' | '\'' => {
+                out.push(ch);
+                while let Some(next) = chars.next() {
+                    out.push(next);
+                    if next == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            out.push(escaped);
+                        }
+                    } else if next == ch || next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '}' => {
+                if out.ends_with(';') {
+                    out.pop();
+                }
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn is_js_ident(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$' || !ch.is_ascii()
+}
+
+/// Keywords that can be followed by an expression, so a `/` after them starts a regular expression.
+const REGEX_KEYWORDS: &[&str] = &[
+    "return",
+    "typeof",
+    "instanceof",
+    "in",
+    "of",
+    "new",
+    "delete",
+    "void",
+    "throw",
+    "case",
+    "do",
+    "else",
+    "yield",
+    "await",
+];
+
+/// Keywords followed by a parenthesized condition, after which a `/` starts a regular expression, as in
+/// `if (x) /a/.test(y)`.
+const CONDITION_KEYWORDS: &[&str] = &["if", "while", "for", "with"];
+
+/// Minifies JavaScript by removing comments and unnecessary whitespace.
+///
+/// Line breaks are kept wherever they could affect automatic semicolon insertion, and the contents of
+/// strings, template literals and regular expressions are never modified.
+pub fn minify_js(js: &str) -> String {
+    #[derive(PartialEq, PartialOrd)]
+    enum Pending {
+        None,
+        Space,
+        Newline,
+    }
+
+    let chars: Vec<char> = js.chars().collect();
+    let mut out = String::with_capacity(js.len());
+    let mut pending = Pending::None;
+    // the brace depth inside each `${` of the template literals currently open
+    let mut templates: Vec<usize> = Vec::new();
+    let mut in_template = false;
+    // whether a `/` would start a regular expression rather than a division, based on the previous token
+    let mut regex_allowed = true;
+    // whether the previous token was a keyword such as `if`, and for each open parenthesis, whether it followed one
+    let mut condition_keyword = false;
+    let mut parens: Vec<bool> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+
+        if in_template {
+            out.push(ch);
+            i += 1;
+            if ch == '\\' {
+                if let Some(&escaped) = chars.get(i) {
+                    out.push(escaped);
+                    i += 1;
+                }
+            } else if ch == '`' {
+                in_template = false;
+                regex_allowed = false;
+            } else if ch == '$' && chars.get(i) == Some(&'{') {
+                out.push('{');
+                i += 1;
+                templates.push(0);
+                in_template = false;
+                regex_allowed = true;
+            }
+            continue;
+        }
+
+        if ch.is_whitespace() {
+            let is_newline = matches!(ch, '\n' | '\r' | '\u{2028}' | '\u{2029}');
+            let new = if is_newline { Pending::Newline } else { Pending::Space };
+            if new > pending {
+                pending = new;
+            }
+            i += 1;
+            continue;
+        }
+        if ch == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && !matches!(chars[i], '\n' | '\r' | '\u{2028}' | '\u{2029}') {
+                i += 1;
+            }
+            if pending < Pending::Space {
+                pending = Pending::Space;
+            }
+            continue;
+        }
+        if ch == '/' && chars.get(i + 1) == Some(&'*') {
+            let mut has_newline = false;
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                has_newline |= matches!(chars[i], '\n' | '\r' | '\u{2028}' | '\u{2029}');
+                i += 1;
+            }
+            i += 2;
+            let new = if has_newline { Pending::Newline } else { Pending::Space };
+            if new > pending {
+                pending = new;
+            }
+            continue;
+        }
+
+        if let Some(prev) = out.chars().last() {
+            match pending {
+                Pending::Newline => {
+                    if !";{,([".contains(prev) && !"}]),;".contains(ch) {
+                        out.push('\n');
+                    }
+                }
+                Pending::Space => {
+                    let needs_space = (is_js_ident(prev) && is_js_ident(ch))
+                        || (prev == ch && matches!(ch, '+' | '-' | '/'))
+                        || (prev.is_ascii_digit() && ch == '.');
+                    if needs_space {
+                        out.push(' ');
+                    }
+                }
+                Pending::None => {}
+            }
+        }
+        pending = Pending::None;
+
+        let was_condition_keyword = std::mem::take(&mut condition_keyword);
+        match ch {
+            _ if is_js_ident(ch) => {
+                // identifiers, keywords and numbers are copied whole, so that keywords can be recognized
+                let after_dot = out.ends_with('.') && !out.ends_with("...");
+                let start = i;
+                while i < chars.len() && is_js_ident(chars[i]) {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                regex_allowed = !after_dot && REGEX_KEYWORDS.contains(&word.as_str());
+                condition_keyword = !after_dot && CONDITION_KEYWORDS.contains(&word.as_str());
+                out.push_str(&word);
+            }
+            '"' | '\'' => {
+                out.push(ch);
+                i += 1;
+                while i < chars.len() {
+                    let next = chars[i];
+                    out.push(next);
+                    i += 1;
+                    if next == '\\' {
+                        if let Some(&escaped) = chars.get(i) {
+                            out.push(escaped);
+                            i += 1;
+                        }
+                    } else if next == ch || next == '\n' {
+                        break;
+                    }
+                }
+                regex_allowed = false;
+            }
+            '`' => {
+                out.push(ch);
+                i += 1;
+                in_template = true;
+            }
+            '/' if regex_allowed => {
+                out.push(ch);
+                i += 1;
+                let mut in_class = false;
+                while i < chars.len() {
+                    let next = chars[i];
+                    if next == '\n' {
+                        break;
+                    }
+                    out.push(next);
+                    i += 1;
+                    match next {
+                        '\\' => {
+                            if let Some(&escaped) = chars.get(i) {
+                                out.push(escaped);
+                                i += 1;
+                            }
+                        }
+                        '[' => in_class = true,
+                        ']' => in_class = false,
+                        '/' if !in_class => break,
+                        _ => {}
+                    }
+                }
+                regex_allowed = false;
+            }
+            '+' | '-' if chars.get(i + 1) == Some(&ch) => {
+                // `++` and `--` are only followed by a `/` when they are postfix operators, as in `i++ / 2`
+                out.push(ch);
+                out.push(ch);
+                i += 2;
+                regex_allowed = false;
+            }
+            '(' => {
+                parens.push(was_condition_keyword);
+                out.push(ch);
+                i += 1;
+                regex_allowed = true;
+            }
+            ')' => {
+                out.push(ch);
+                i += 1;
+                regex_allowed = parens.pop().unwrap_or(false);
+            }
+            ']' => {
+                out.push(ch);
+                i += 1;
+                regex_allowed = false;
+            }
+            '{' => {
+                if let Some(depth) = templates.last_mut() {
+                    *depth += 1;
+                }
+                out.push(ch);
+                i += 1;
+                regex_allowed = true;
+            }
+            '}' => {
+                out.push(ch);
+                i += 1;
+                // a `}` usually ends a block, which is followed by a new statement
+                regex_allowed = true;
+                match templates.last_mut() {
+                    Some(0) => {
+                        templates.pop();
+                        in_template = true;
+                    }
+                    Some(depth) => *depth -= 1,
+                    None => {}
+                }
+            }
+            _ => {
+                out.push(ch);
+                i += 1;
+                regex_allowed = true;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn js_strings_are_kept() {
+        assert_eq!(minify_js("var s = \"a  b\" + 'c  d';"), "var s=\"a  b\"+'c  d';");
+        assert_eq!(minify_js("s = 'it\\'s  //  here'"), "s='it\\'s  //  here'");
+    }
+
+    #[test]
+    fn js_division_after_operands() {
+        assert_eq!(minify_js("i++ / 2; s = \"a/b  c\""), "i++/2;s=\"a/b  c\"");
+        assert_eq!(minify_js("(a + b) / 2 / c"), "(a+b)/2/c");
+        assert_eq!(minify_js("x[0] / y"), "x[0]/y");
+        assert_eq!(minify_js("a.return / 2 / 'b  c'"), "a.return/2/'b  c'");
+    }
+
+    #[test]
+    fn js_regexes_are_kept() {
+        assert_eq!(minify_js("if (x) /[ ]'/.test(s)"), "if(x)/[ ]'/.test(s)");
+        assert_eq!(minify_js("return /a b/g.test(x)"), "return/a b/g.test(x)");
+        assert_eq!(minify_js("x = /[/]  \\/ /; y = 'a  b'"), "x=/[/]  \\/ /;y='a  b'");
+        assert_eq!(minify_js("f(a, / b /)"), "f(a,/ b /)");
+    }
+
+    #[test]
+    fn js_template_literals() {
+        assert_eq!(minify_js("`a  ${ b  +  `c  ${ d }` }  e` / 2"), "`a  ${b+`c  ${d}`}  e`/2");
+        assert_eq!(minify_js("`${ {a: 1}.a }  x`"), "`${{a:1}.a}  x`");
+    }
+
+    #[test]
+    fn js_automatic_semicolon_insertion() {
+        assert_eq!(minify_js("a = b\n++c"), "a=b\n++c");
+        assert_eq!(minify_js("return\nx"), "return\nx");
+        assert_eq!(minify_js("f(a,\n  b)\n{\n  x;\n}"), "f(a,b)\n{x;}");
+        assert_eq!(minify_js("a + +b; a - -b; a+ ++b"), "a+ +b;a- -b;a+ ++b");
+    }
+
+    #[test]
+    fn js_comments_are_removed() {
+        assert_eq!(minify_js("a // x\nb /* y */ c"), "a\nb c");
+        assert_eq!(minify_js("a /* x\n */ b"), "a\nb");
+    }
+
+    #[test]
+    fn css_whitespace_and_comments() {
+        assert_eq!(minify_css("a  >  b { color: red ; /* x */ }"), "a>b{color:red}");
+        assert_eq!(
+            minify_css("a::before { content: \"a  ;  b\" }"),
+            "a::before{content:\"a  ;  b\"}"
+        );
+        assert_eq!(minify_css("a:hover .b { margin: 0 auto }"), "a:hover .b{margin:0 auto}");
+        // a space before `:` separates a descendant selector from a pseudo-class
+        assert_eq!(minify_css("a :hover{}"), "a :hover{}");
+    }
+}
@@ -1,26 +1,32 @@
-use anyhow::Result;
-use kuchikiki::NodeRef;
-use tidier::{Doc, FormatOptions, Indent, LineEnding};
+use kuchikiki::{NodeData, NodeRef};
 
+pub mod diff;
 pub mod extract_text;
 pub mod is_document;
+pub mod markdown;
+pub mod minify;
+pub mod parse_errors;
+pub mod pretty_print;
+pub mod sanitize;
+pub mod sniff_encoding;
+pub mod source_location;
+pub mod style;
+pub mod xml;
 
-pub fn pretty_print(text: &str) -> Result<String> {
-    let opts = FormatOptions {
-        indent: Indent::DEFAULT,
-        eol: LineEnding::Lf,
-        wrap: 120,
-        join_classes: true,
-        join_styles: true,
-        ..FormatOptions::DEFAULT
-    };
-    let doc = Doc::new(text, false)?;
-    doc.repair()?;
-    Ok(doc.format(&opts)?)
+/// Creates the tree sink kuchikiki's HTML parser uses, so that custom sinks can wrap it.
+pub(crate) fn new_sink() -> kuchikiki::Sink {
+    // kuchikiki doesn't expose a constructor for its sink, so it's taken from an unused parser
+    kuchikiki::parse_html().tokenizer.sink.sink
 }
 
 pub fn clone_node(node: &NodeRef) -> NodeRef {
-    let data = node.0.data().clone();
+    let mut data = node.0.data().clone();
+    if let NodeData::Element(elem) = &mut data {
+        // The contents of a `<template>` are kept outside of the tree, so they aren't cloned with the children
+        if let Some(contents) = &elem.template_contents {
+            elem.template_contents = Some(clone_node(contents));
+        }
+    }
     let new_node = NodeRef::new(data);
 
     for child in node.children() {
@@ -0,0 +1,144 @@
+use html5ever::{
+    interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink},
+    namespace_url, ns,
+    tendril::{StrTendril, TendrilSink},
+    tokenizer::TokenizerOpts,
+    tree_builder::TreeBuilderOpts,
+    Attribute, ExpandedName, LocalName, Namespace, ParseOpts, QualName,
+};
+use regex::Regex;
+use std::{borrow::Cow, sync::LazyLock};
+
+pub struct ParseError {
+    pub line: u64,
+    pub message: Cow<'static, str>,
+}
+
+/// A tree sink that only keeps track of what the tree builder needs, and records parse errors with the line
+/// they occurred on.
+#[derive(Default)]
+struct ParseErrorTreeSink {
+    current_line: u64,
+    errors: Vec<ParseError>,
+    elements: Vec<Option<(Namespace, LocalName)>>,
+}
+
+impl ParseErrorTreeSink {
+    fn push(&mut self, element: Option<(Namespace, LocalName)>) -> usize {
+        self.elements.push(element);
+        self.elements.len() - 1
+    }
+}
+
+impl TreeSink for ParseErrorTreeSink {
+    type Handle = usize;
+    type Output = Vec<ParseError>;
+
+    fn finish(self) -> Self::Output {
+        self.errors
+    }
+
+    fn parse_error(&mut self, message: Cow<'static, str>) {
+        let message = clean_message(message);
+        self.errors
+            .push(ParseError { line: self.current_line, message });
+    }
+
+    fn set_current_line(&mut self, line: u64) {
+        self.current_line = line;
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        usize::MAX
+    }
+
+    fn elem_name<'a>(&'a self, h: &'a Self::Handle) -> ExpandedName<'a> {
+        let t = &self.elements[*h];
+        let t = t.as_ref().unwrap();
+        ExpandedName { ns: &t.0, local: &t.1 }
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        _: Vec<Attribute>,
+        _: ElementFlags,
+    ) -> Self::Handle {
+        self.push(Some((name.ns, name.local)))
+    }
+
+    fn create_comment(&mut self, _: StrTendril) -> Self::Handle {
+        self.push(None)
+    }
+
+    fn create_pi(&mut self, _: StrTendril, _: StrTendril) -> Self::Handle {
+        self.push(None)
+    }
+
+    fn append(&mut self, _: &Self::Handle, _: NodeOrText<Self::Handle>) {}
+
+    fn append_based_on_parent_node(
+        &mut self,
+        _: &Self::Handle,
+        _: &Self::Handle,
+        _: NodeOrText<Self::Handle>,
+    ) {
+    }
+
+    fn append_doctype_to_document(&mut self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn get_template_contents(&mut self, _: &Self::Handle) -> Self::Handle {
+        self.push(None)
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&mut self, _: QuirksMode) {}
+
+    fn append_before_sibling(&mut self, _: &Self::Handle, _: NodeOrText<Self::Handle>) {}
+
+    fn add_attrs_if_missing(&mut self, _: &Self::Handle, _: Vec<Attribute>) {}
+
+    fn remove_from_parent(&mut self, _: &Self::Handle) {}
+
+    fn reparent_children(&mut self, _: &Self::Handle, _: &Self::Handle) {}
+}
+
+static TAG_TOKEN: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"Tag \{ kind: (StartTag|EndTag), name: Atom\(\\?'([^'\\]*)\\?' type=\w+\), .*?attrs: \[.*?\] \}")
+        .unwrap()
+});
+static HTML_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{http://www\.w3\.org/1999/xhtml\}:(\w+)").unwrap());
+
+/// Replaces the debug representations of tokens in html5ever's detailed error messages with tags.
+fn clean_message(message: Cow<'static, str>) -> Cow<'static, str> {
+    if !message.contains('{') {
+        return message;
+    }
+    let message = TAG_TOKEN.replace_all(&message, |captures: &regex::Captures| {
+        let slash = if &captures[1] == "EndTag" { "/" } else { "" };
+        format!("<{slash}{}>", &captures[2])
+    });
+    let message = HTML_NAME.replace_all(&message, "<$1>");
+    Cow::Owned(message.into_owned())
+}
+
+/// Parses a document or fragment, returning the parse errors found along with the line they occurred on.
+pub fn parse_errors(source: &str, fragment_root: Option<&str>) -> Vec<ParseError> {
+    let opts = ParseOpts {
+        tokenizer: TokenizerOpts { exact_errors: true, ..Default::default() },
+        tree_builder: TreeBuilderOpts { exact_errors: true, ..Default::default() },
+    };
+    // html5ever only reports line changes, so the sink must start on the first line
+    let sink = ParseErrorTreeSink { current_line: 1, ..Default::default() };
+    match fragment_root {
+        None => html5ever::parse_document(sink, opts).one(source),
+        Some(root) => {
+            let root = QualName::new(None, ns!(html), LocalName::from(root));
+            html5ever::parse_fragment(sink, opts, root, vec![]).one(source)
+        }
+    }
+}
@@ -0,0 +1,461 @@
+use crate::html::minify::{is_inline, is_raw_text, is_void, is_whitespace_sensitive};
+use html5ever::{namespace_url, ns};
+use kuchikiki::{ElementData, NodeData, NodeRef};
+
+/// Options for [`pretty_print`].
+#[derive(Clone, Debug)]
+pub struct PrettyPrintOptions {
+    /// The number of spaces that nested block elements are indented by.
+    pub indent: usize,
+    /// The column that text is wrapped at, or 0 to never wrap text.
+    pub wrap: usize,
+    /// Whether start tags that don't fit in the wrap column are split to one attribute per line.
+    pub wrap_attributes: bool,
+    /// Elements whose contents are written exactly as they were parsed, in addition to the whitespace-sensitive
+    /// elements (such as `<pre>`) that always are.
+    pub preserve: Vec<String>,
+}
+impl Default for PrettyPrintOptions {
+    fn default() -> Self {
+        PrettyPrintOptions {
+            indent: 2,
+            wrap: 120,
+            wrap_attributes: false,
+            preserve: vec!["code".to_string()],
+        }
+    }
+}
+
+/// A piece of inline content. Lines are only ever broken at [`Piece::Space`], which stands for whitespace that
+/// was already in the document, so wrapping never changes how the page renders.
+enum Piece {
+    Text(String),
+    Space,
+}
+
+fn push_text(pieces: &mut Vec<Piece>, text: &str) {
+    match pieces.last_mut() {
+        Some(Piece::Text(last)) => last.push_str(text),
+        _ => pieces.push(Piece::Text(text.to_string())),
+    }
+}
+
+fn trim_spaces(pieces: &[Piece]) -> &[Piece] {
+    let start = pieces
+        .iter()
+        .position(|x| matches!(x, Piece::Text(_)))
+        .unwrap_or(pieces.len());
+    let end = pieces
+        .iter()
+        .rposition(|x| matches!(x, Piece::Text(_)))
+        .map_or(start, |x| x + 1);
+    &pieces[start..end]
+}
+
+fn push_escaped(out: &mut String, text: &str, attribute: bool) {
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '<' if !attribute => out.push_str("&lt;"),
+            '>' if !attribute => out.push_str("&gt;"),
+            _ => out.push(ch),
+        }
+    }
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// The column a line ends on after `text` is written starting at `column`.
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(pos) => width(&text[pos + 1..]),
+        None => column + width(text),
+    }
+}
+
+fn contents(node: &NodeRef, elem: &ElementData) -> NodeRef {
+    match &elem.template_contents {
+        Some(contents) => contents.clone(),
+        None => node.clone(),
+    }
+}
+
+fn attributes(elem: &ElementData) -> Vec<String> {
+    let mut list = Vec::new();
+    for (attr_name, attr) in elem.attributes.borrow().map.iter() {
+        let mut out = String::new();
+        if attr_name.ns == ns!(xml) {
+            out.push_str("xml:");
+        } else if attr_name.ns == ns!(xmlns) && &*attr_name.local != "xmlns" {
+            out.push_str("xmlns:");
+        } else if attr_name.ns == ns!(xlink) {
+            out.push_str("xlink:");
+        } else if let Some(prefix) = &attr.prefix {
+            out.push_str(prefix);
+            out.push(':');
+        }
+        out.push_str(&attr_name.local);
+        if !attr.value.is_empty() {
+            out.push_str("=\"");
+            push_escaped(&mut out, &attr.value, true);
+            out.push('"');
+        }
+        list.push(out);
+    }
+    list
+}
+
+fn start_tag(elem: &ElementData) -> String {
+    let mut out = format!("<{}", elem.name.borrow().local);
+    for attr in attributes(elem) {
+        out.push(' ');
+        out.push_str(&attr);
+    }
+    out.push('>');
+    out
+}
+
+/// Serializes a node exactly as it is, for the contents of preserved elements.
+fn verbatim(out: &mut String, node: &NodeRef) {
+    match node.data() {
+        NodeData::Element(elem) => {
+            let name = elem.name.borrow().local.clone();
+            let html = elem.name.borrow().ns == ns!(html);
+            out.push_str(&start_tag(elem));
+            if html && is_void(&name) {
+                return;
+            }
+
+            let children = contents(node, elem);
+            if html && matches!(&*name, "pre" | "textarea" | "listing") {
+                // the parser ignores a newline directly after these tags, so it must be doubled
+                if let Some(text) = children.first_child().as_ref().and_then(|x| x.as_text()) {
+                    if text.borrow().starts_with('\n') {
+                        out.push('\n');
+                    }
+                }
+            }
+            for child in children.children() {
+                match child.as_text() {
+                    Some(text) if html && is_raw_text(&name) => out.push_str(&text.borrow()),
+                    _ => verbatim(out, &child),
+                }
+            }
+            out.push_str("</");
+            out.push_str(&name);
+            out.push('>');
+        }
+        NodeData::Text(text) => push_escaped(out, &text.borrow(), false),
+        NodeData::Comment(text) => {
+            out.push_str("<!--");
+            out.push_str(&text.borrow());
+            out.push_str("-->");
+        }
+        NodeData::ProcessingInstruction(contents) => {
+            let contents = contents.borrow();
+            out.push_str("<?");
+            out.push_str(&contents.0);
+            out.push(' ');
+            out.push_str(&contents.1);
+            out.push('>');
+        }
+        NodeData::Doctype(doctype) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(&doctype.name);
+            out.push('>');
+        }
+        NodeData::Document(_) | NodeData::DocumentFragment => {
+            for child in node.children() {
+                verbatim(out, &child);
+            }
+        }
+    }
+}
+
+struct PrettyPrinter<'a> {
+    options: &'a PrettyPrintOptions,
+    out: String,
+}
+impl PrettyPrinter<'_> {
+    fn push_indent(&mut self, depth: usize) {
+        for _ in 0..depth * self.options.indent {
+            self.out.push(' ');
+        }
+    }
+
+    fn fits(&self, column: usize) -> bool {
+        self.options.wrap == 0 || column <= self.options.wrap
+    }
+
+    /// Whether an element's contents are written as they are. Raw text, whitespace-sensitive and foreign (SVG or
+    /// MathML) elements are always preserved.
+    fn is_preserved(&self, elem: &ElementData) -> bool {
+        let name = elem.name.borrow();
+        name.ns != ns!(html)
+            || is_raw_text(&name.local)
+            || is_whitespace_sensitive(&name.local)
+            || self
+                .options
+                .preserve
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(&name.local))
+    }
+
+    /// Whether a node is written on lines of its own. Inline elements that contain block elements count as blocks
+    /// themselves.
+    fn is_block(&self, node: &NodeRef) -> bool {
+        match node.data() {
+            NodeData::Element(elem) => {
+                if elem.name.borrow().ns != ns!(html) {
+                    false
+                } else if !is_inline(&elem.name.borrow().local) {
+                    true
+                } else {
+                    !self.is_preserved(elem)
+                        && contents(node, elem).children().any(|x| self.is_block(&x))
+                }
+            }
+            NodeData::Doctype(_) | NodeData::ProcessingInstruction(_) => true,
+            _ => false,
+        }
+    }
+
+    fn inline(&self, node: &NodeRef, pieces: &mut Vec<Piece>) {
+        match node.data() {
+            NodeData::Text(text) => {
+                let text = text.borrow();
+                let mut word = String::new();
+                for ch in text.chars() {
+                    if ch.is_ascii_whitespace() {
+                        if !word.is_empty() {
+                            push_text(pieces, &word);
+                            word.clear();
+                        }
+                        if !matches!(pieces.last(), Some(Piece::Space)) {
+                            pieces.push(Piece::Space);
+                        }
+                    } else {
+                        push_escaped(&mut word, ch.encode_utf8(&mut [0; 4]), false);
+                    }
+                }
+                if !word.is_empty() {
+                    push_text(pieces, &word);
+                }
+            }
+            NodeData::Element(elem) if !self.is_preserved(elem) => {
+                push_text(pieces, &start_tag(elem));
+                let name = elem.name.borrow().local.clone();
+                if is_void(&name) {
+                    return;
+                }
+                for child in contents(node, elem).children() {
+                    self.inline(&child, pieces);
+                }
+                push_text(pieces, &format!("</{name}>"));
+            }
+            _ => {
+                let mut out = String::new();
+                verbatim(&mut out, node);
+                push_text(pieces, &out);
+            }
+        }
+    }
+
+    /// Writes a run of inline content, wrapping it at the spaces in it.
+    fn run(&mut self, pieces: &[Piece], depth: usize) {
+        let pieces = trim_spaces(pieces);
+        if pieces.is_empty() {
+            return;
+        }
+
+        let indent = depth * self.options.indent;
+        self.push_indent(depth);
+        let mut column = indent;
+        let mut pending_space = false;
+        for piece in pieces {
+            match piece {
+                Piece::Space => pending_space = true,
+                Piece::Text(text) => {
+                    if pending_space {
+                        let first_line = text.split('\n').next().unwrap_or_default();
+                        if column > indent && !self.fits(column + 1 + width(first_line)) {
+                            self.out.push('\n');
+                            self.push_indent(depth);
+                            column = indent;
+                        } else {
+                            self.out.push(' ');
+                            column += 1;
+                        }
+                        pending_space = false;
+                    }
+                    self.out.push_str(text);
+                    column = end_column(column, text);
+                }
+            }
+        }
+        self.out.push('\n');
+    }
+
+    /// Writes the children of a node, with block children on their own lines and the inline content between them
+    /// gathered into runs.
+    fn children(&mut self, parent: &NodeRef, depth: usize) {
+        let mut pieces = Vec::new();
+        for child in parent.children() {
+            if self.is_block(&child) {
+                self.run(&pieces, depth);
+                pieces.clear();
+                self.block(&child, depth);
+            } else {
+                self.inline(&child, &mut pieces);
+            }
+        }
+        self.run(&pieces, depth);
+    }
+
+    fn wrapped_start_tag(&self, elem: &ElementData, depth: usize) -> String {
+        let single_line = start_tag(elem);
+        let attrs = attributes(elem);
+        let column = depth * self.options.indent + width(&single_line);
+        if !self.options.wrap_attributes || attrs.len() < 2 || self.fits(column) {
+            return single_line;
+        }
+
+        let mut out = format!("<{}", elem.name.borrow().local);
+        for attr in attrs {
+            out.push('\n');
+            for _ in 0..(depth + 1) * self.options.indent {
+                out.push(' ');
+            }
+            out.push_str(&attr);
+        }
+        out.push('>');
+        out
+    }
+
+    fn block(&mut self, node: &NodeRef, depth: usize) {
+        let elem = match node.data() {
+            NodeData::Element(elem) if !self.is_preserved(elem) => elem,
+            _ => {
+                self.push_indent(depth);
+                verbatim(&mut self.out, node);
+                self.out.push('\n');
+                return;
+            }
+        };
+
+        let name = elem.name.borrow().local.clone();
+        let start = self.wrapped_start_tag(elem, depth);
+        let end = format!("</{name}>");
+        self.push_indent(depth);
+        self.out.push_str(&start);
+        if is_void(&name) {
+            self.out.push('\n');
+            return;
+        }
+
+        let children = contents(node, elem);
+        if children.children().any(|x| self.is_block(&x)) {
+            self.out.push('\n');
+            self.children(&children, depth + 1);
+            self.push_indent(depth);
+        } else {
+            let mut pieces = Vec::new();
+            for child in children.children() {
+                self.inline(&child, &mut pieces);
+            }
+            let pieces = trim_spaces(&pieces);
+
+            // Short contents are kept on the same line as the tags
+            let mut single_line = String::new();
+            for piece in pieces {
+                match piece {
+                    Piece::Text(text) => single_line.push_str(text),
+                    Piece::Space => single_line.push(' '),
+                }
+            }
+            let column = end_column(depth * self.options.indent, &start);
+            let is_short = !single_line.contains('\n')
+                && self.fits(column + width(&single_line) + width(&end));
+            if is_short {
+                self.out.push_str(&single_line);
+            } else {
+                self.out.push('\n');
+                self.run(pieces, depth + 1);
+                self.push_indent(depth);
+            }
+        }
+        self.out.push_str(&end);
+        self.out.push('\n');
+    }
+}
+
+/// Serializes a node as indented HTML.
+///
+/// Block elements are written on lines of their own, and are indented by their depth. Inline content is
+/// collapsed and wrapped at the wrap column, but only at whitespace that was already in the document, so the page
+/// renders the same as it did before. The contents of preserved elements, as well as whitespace-sensitive elements
+/// (such as `<pre>`), `<script>`, `<style>` and SVG or MathML content, are written exactly as they are.
+pub fn pretty_print(node: &NodeRef, options: &PrettyPrintOptions) -> String {
+    let mut printer = PrettyPrinter { options, out: String::new() };
+    match node.data() {
+        NodeData::Document(_) | NodeData::DocumentFragment => printer.children(node, 0),
+        _ if printer.is_block(node) => printer.block(node, 0),
+        _ => {
+            let mut pieces = Vec::new();
+            printer.inline(node, &mut pieces);
+            printer.run(&pieces, 0);
+        }
+    }
+    printer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kuchikiki::traits::TendrilSink;
+
+    fn print(html: &str, options: &PrettyPrintOptions) -> String {
+        let document = kuchikiki::parse_html().one(html);
+        pretty_print(&document.select_first("body").unwrap().as_node().clone(), options)
+    }
+
+    #[test]
+    fn blocks_are_indented() {
+        let options = PrettyPrintOptions::default();
+        assert_eq!(
+            print("<div><p>Some <em>text</em></p><ul><li>a</li></ul></div>", &options),
+            "<body>\n  <div>\n    <p>Some <em>text</em></p>\n    <ul>\n      <li>a</li>\n    </ul>\n  </div>\n</body>\n"
+        );
+    }
+
+    #[test]
+    fn whitespace_sensitive_elements_are_always_preserved() {
+        let options = PrettyPrintOptions { preserve: vec![], ..Default::default() };
+        assert_eq!(
+            print("<div><pre>  a\n    <b>b</b></pre><textarea>  x  </textarea></div>", &options),
+            "<body>\n  <div>\n    <pre>  a\n    <b>b</b></pre>\n    <textarea>  x  </textarea>\n  </div>\n</body>\n"
+        );
+    }
+
+    #[test]
+    fn text_is_wrapped_at_existing_whitespace() {
+        let options = PrettyPrintOptions { wrap: 20, ..Default::default() };
+        assert_eq!(
+            print("<p>one two three four five six seven<b>eight</b>nine</p>", &options),
+            "<body>\n  <p>\n    one two three\n    four five six\n    seven<b>eight</b>nine\n  </p>\n</body>\n"
+        );
+    }
+
+    #[test]
+    fn preserved_elements_are_written_verbatim() {
+        let options = PrettyPrintOptions::default();
+        assert_eq!(
+            print("<p>a <code>b   c</code>  d</p>", &options),
+            "<body>\n  <p>a <code>b   c</code> d</p>\n</body>\n"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::wyhash::{WyHashMap, WyHashSet};
+use html5ever::{namespace_url, ns};
+use kuchikiki::{ElementData, NodeData, NodeRef};
+
+/// Which elements, attributes and links are kept by [`sanitize`]. Element and attribute names are lowercase.
+#[derive(Clone, Debug)]
+pub struct SanitizePolicy {
+    /// Elements that are kept. Other elements are replaced by their contents.
+    pub tags: WyHashSet<String>,
+    /// Elements that are removed along with their contents.
+    pub remove_tags: WyHashSet<String>,
+    /// The attributes kept on each element, with attributes allowed on every element under `*`.
+    pub attributes: WyHashMap<String, WyHashSet<String>>,
+    /// The URL schemes allowed in links. Relative URLs are always allowed.
+    pub url_schemes: WyHashSet<String>,
+    /// The `rel` attribute set on every link, if it isn't empty.
+    pub link_rel: String,
+    pub strip_comments: bool,
+}
+
+fn set(list: &[&str]) -> WyHashSet<String> {
+    list.iter().map(|x| x.to_string()).collect()
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> Self {
+        let tags = set(&[
+            "a",
+            "abbr",
+            "b",
+            "blockquote",
+            "br",
+            "caption",
+            "cite",
+            "code",
+            "col",
+            "colgroup",
+            "dd",
+            "del",
+            "details",
+            "dfn",
+            "div",
+            "dl",
+            "dt",
+            "em",
+            "figcaption",
+            "figure",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "hr",
+            "i",
+            "img",
+            "ins",
+            "kbd",
+            "li",
+            "mark",
+            "ol",
+            "p",
+            "pre",
+            "q",
+            "s",
+            "samp",
+            "small",
+            "span",
+            "strong",
+            "sub",
+            "summary",
+            "sup",
+            "table",
+            "tbody",
+            "td",
+            "tfoot",
+            "th",
+            "thead",
+            "time",
+            "tr",
+            "u",
+            "ul",
+            "var",
+        ]);
+        let mut attributes = WyHashMap::default();
+        for (tag, list) in [
+            ("*", &["dir", "lang", "title"][..]),
+            ("a", &["href", "hreflang"]),
+            ("blockquote", &["cite"]),
+            ("col", &["span"]),
+            ("colgroup", &["span"]),
+            ("del", &["cite", "datetime"]),
+            ("img", &["alt", "height", "src", "width"]),
+            ("ins", &["cite", "datetime"]),
+            ("ol", &["reversed", "start"]),
+            ("q", &["cite"]),
+            ("td", &["colspan", "rowspan"]),
+            ("th", &["colspan", "rowspan", "scope"]),
+            ("time", &["datetime"]),
+        ] {
+            attributes.insert(tag.to_string(), set(list));
+        }
+
+        SanitizePolicy {
+            tags,
+            remove_tags: set(&[
+                "script", "style", "template", "noscript", "iframe", "object", "embed", "frameset",
+            ]),
+            attributes,
+            url_schemes: set(&["http", "https", "mailto"]),
+            link_rel: "noopener noreferrer".to_string(),
+            strip_comments: true,
+        }
+    }
+}
+
+fn is_url_attribute(name: &str) -> bool {
+    matches!(
+        name,
+        "href" | "src" | "cite" | "action" | "formaction" | "poster" | "background"
+    )
+}
+
+/// Checks a URL's scheme against the policy. Browsers ignore tabs and newlines in URLs, so they are ignored
+/// here as well, to catch URLs like `java&#9;script:`.
+fn is_allowed_url(url: &str, policy: &SanitizePolicy) -> bool {
+    let url: String = url
+        .trim_matches(|x: char| x <= ' ')
+        .chars()
+        .filter(|x| !matches!(x, '\t' | '\n' | '\r'))
+        .collect();
+    let Some(colon) = url.find(':') else {
+        return true;
+    };
+    let scheme = &url[..colon];
+    let is_scheme = scheme.starts_with(|x: char| x.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || matches!(x, '+' | '-' | '.'));
+    if !is_scheme {
+        // the colon is part of a path, query or fragment, as in `./a:b` or `?time=12:00`
+        return true;
+    }
+    policy.url_schemes.contains(&scheme.to_ascii_lowercase())
+}
+
+fn is_allowed_srcset(srcset: &str, policy: &SanitizePolicy) -> bool {
+    srcset.split(',').all(|candidate| {
+        is_allowed_url(candidate.trim().split(' ').next().unwrap_or_default(), policy)
+    })
+}
+
+fn sanitize_attributes(elem: &ElementData, name: &str, policy: &SanitizePolicy) {
+    let allowed = |attr: &str| {
+        [name, "*"].iter().any(|tag| {
+            policy
+                .attributes
+                .get(*tag)
+                .is_some_and(|x| x.contains(attr))
+        })
+    };
+
+    let mut attrs = elem.attributes.borrow_mut();
+    attrs.map.retain(|attr_name, attr| {
+        // Attributes added with `set_attribute` may not be lowercase, unlike parsed ones
+        let local = &*attr_name.local.to_ascii_lowercase();
+        attr_name.ns == ns!()
+            && allowed(local)
+            && match local {
+                "srcset" => is_allowed_srcset(&attr.value, policy),
+                _ if is_url_attribute(local) => is_allowed_url(&attr.value, policy),
+                _ => true,
+            }
+    });
+    if name == "a" && !policy.link_rel.is_empty() && attrs.contains("href") {
+        attrs.insert("rel", policy.link_rel.clone());
+    }
+}
+
+fn sanitize_children(parent: &NodeRef, policy: &SanitizePolicy) {
+    for child in parent.children().collect::<Vec<_>>() {
+        match child.data() {
+            NodeData::Element(elem) => {
+                let name = elem.name.borrow().local.to_ascii_lowercase();
+                let is_html = elem.name.borrow().ns == ns!(html);
+                if !is_html || policy.remove_tags.contains(&*name) {
+                    child.detach();
+                } else if !policy.tags.contains(&*name) {
+                    // Disallowed elements are replaced by their (sanitized) contents
+                    sanitize_children(&child, policy);
+                    for grandchild in child.children().collect::<Vec<_>>() {
+                        child.insert_before(grandchild);
+                    }
+                    child.detach();
+                } else {
+                    sanitize_attributes(elem, &name, policy);
+                    if let Some(contents) = &elem.template_contents {
+                        sanitize_children(contents, policy);
+                    }
+                    sanitize_children(&child, policy);
+                }
+            }
+            NodeData::Comment(_) if policy.strip_comments => child.detach(),
+            NodeData::ProcessingInstruction(_) => child.detach(),
+            _ => {}
+        }
+    }
+}
+
+/// Removes everything from the contents of a node that the policy doesn't allow, so that HTML from untrusted
+/// sources can be included in a page.
+///
+/// Elements that aren't allowed are replaced by their contents, except for the ones in `remove_tags` (such as
+/// `<script>`) and SVG or MathML content, which are removed entirely. Attributes are only kept if they are
+/// allowed, and links are only kept if they are relative or use an allowed scheme.
+pub fn sanitize(node: &NodeRef, policy: &SanitizePolicy) {
+    sanitize_children(node, policy);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kuchikiki::traits::TendrilSink;
+
+    fn sanitized(html: &str, policy: &SanitizePolicy) -> String {
+        let document = kuchikiki::parse_html().one(html);
+        let body = document.select_first("body").unwrap().as_node().clone();
+        sanitize(&body, policy);
+        let mut out = String::new();
+        for child in body.children() {
+            out.push_str(&child.to_string());
+        }
+        out
+    }
+
+    #[test]
+    fn disallowed_elements_are_unwrapped_or_removed() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(
+            sanitized("<p>a<font>b<script>x</script></font><!-- c --></p>", &policy),
+            "<p>ab</p>"
+        );
+        assert_eq!(sanitized("<p><svg><a href=x>y</a></svg>z</p>", &policy), "<p>z</p>");
+    }
+
+    #[test]
+    fn attributes_are_filtered() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(
+            sanitized("<p lang=en onclick=x style=y>a</p>", &policy),
+            "<p lang=\"en\">a</p>"
+        );
+        assert_eq!(
+            sanitized("<img src=a.png alt=a onerror=x>", &policy),
+            "<img src=\"a.png\" alt=\"a\">"
+        );
+    }
+
+    #[test]
+    fn unsafe_urls_are_removed() {
+        let policy = SanitizePolicy::default();
+        assert_eq!(sanitized("<a href=\" java&#9;script:alert(1)\">a</a>", &policy), "<a>a</a>");
+        assert_eq!(
+            sanitized("<a href=\"HTTPS://example.com\">a</a>", &policy),
+            "<a href=\"HTTPS://example.com\" rel=\"noopener noreferrer\">a</a>"
+        );
+        assert_eq!(
+            sanitized("<a href=\"./a:b?t=12:00\">a</a>", &policy),
+            "<a href=\"./a:b?t=12:00\" rel=\"noopener noreferrer\">a</a>"
+        );
+        assert_eq!(sanitized("<img srcset=\"a.png 1x, javascript:x 2x\">", &policy), "<img>");
+    }
+
+    #[test]
+    fn added_attributes_are_case_insensitive() {
+        let policy = SanitizePolicy::default();
+        let document = kuchikiki::parse_html().one("<a>a</a><a>b</a>");
+        let body = document.select_first("body").unwrap().as_node().clone();
+        let links: Vec<_> = body.select("a").unwrap().collect();
+        links[0]
+            .attributes
+            .borrow_mut()
+            .insert("HREF", "https://example.com".to_string());
+        links[1]
+            .attributes
+            .borrow_mut()
+            .insert("HREF", "javascript:x".to_string());
+        sanitize(&body, &policy);
+        assert!(links[0].attributes.borrow().contains("HREF"));
+        assert!(!links[1].attributes.borrow().contains("HREF"));
+    }
+}
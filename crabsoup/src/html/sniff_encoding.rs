@@ -0,0 +1,346 @@
+use crate::html::clone_node;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+use kuchikiki::{Attributes, NodeRef};
+
+/// The number of bytes at the start of a document that are searched for a `<meta>` tag, as in browsers.
+const PRESCAN_LENGTH: usize = 1024;
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+/// A cursor over the bytes being prescanned, following the algorithms of the HTML standard.
+struct Prescan<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl Prescan<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, prefix: &[u8]) -> bool {
+        self.bytes[self.pos..]
+            .get(..prefix.len())
+            .is_some_and(|x| x.eq_ignore_ascii_case(prefix))
+    }
+
+    fn skip_while(&mut self, f: impl Fn(u8) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+    }
+
+    /// Reads the next attribute of a tag, returning `None` at the end of the tag.
+    fn attribute(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.skip_while(|x| is_whitespace(x) || x == b'/');
+        if self.peek().is_none_or(|x| x == b'>') {
+            return None;
+        }
+
+        let mut name = Vec::new();
+        loop {
+            match self.peek()? {
+                b'=' if !name.is_empty() => {
+                    self.pos += 1;
+                    break;
+                }
+                x if is_whitespace(x) => {
+                    self.skip_while(is_whitespace);
+                    if self.peek() != Some(b'=') {
+                        return Some((name, Vec::new()));
+                    }
+                    self.pos += 1;
+                    break;
+                }
+                b'/' | b'>' => return Some((name, Vec::new())),
+                x => name.push(x.to_ascii_lowercase()),
+            }
+            self.pos += 1;
+        }
+
+        self.skip_while(is_whitespace);
+        let mut value = Vec::new();
+        match self.peek()? {
+            quote @ (b'"' | b'\'') => {
+                self.pos += 1;
+                loop {
+                    let x = self.peek()?;
+                    self.pos += 1;
+                    if x == quote {
+                        break;
+                    }
+                    value.push(x.to_ascii_lowercase());
+                }
+            }
+            b'>' => {}
+            _ => {
+                while let Some(x) = self.peek() {
+                    if is_whitespace(x) || x == b'>' {
+                        break;
+                    }
+                    value.push(x.to_ascii_lowercase());
+                    self.pos += 1;
+                }
+            }
+        }
+        Some((name, value))
+    }
+
+    /// Reads the attributes of a `<meta>` tag, and returns the encoding it declares if any.
+    fn meta(&mut self) -> Option<&'static Encoding> {
+        let mut seen = Vec::new();
+        let mut got_pragma = false;
+        let mut need_pragma = None;
+        let mut charset = None;
+        while let Some((name, value)) = self.attribute() {
+            if seen.contains(&name) {
+                continue;
+            }
+            match name.as_slice() {
+                b"http-equiv" => got_pragma |= value == b"content-type",
+                b"content" if charset.is_none() => {
+                    if let Some(encoding) = charset_from_content(&value) {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+                b"charset" => {
+                    charset = Some(Encoding::for_label(&value));
+                    need_pragma = Some(false);
+                }
+                _ => {}
+            }
+            seen.push(name);
+        }
+
+        match need_pragma {
+            None => None,
+            Some(true) if !got_pragma => None,
+            _ => match charset?? {
+                x if x == UTF_16BE || x == UTF_16LE => Some(UTF_8),
+                x if x == X_USER_DEFINED => Some(WINDOWS_1252),
+                x => Some(x),
+            },
+        }
+    }
+
+    fn skip_past(&mut self, needle: &[u8]) {
+        match self.bytes[self.pos..]
+            .windows(needle.len())
+            .position(|x| x == needle)
+        {
+            Some(offset) => self.pos += offset + needle.len(),
+            None => self.pos = self.bytes.len(),
+        }
+    }
+
+    fn run(&mut self) -> Option<&'static Encoding> {
+        while self.pos < self.bytes.len() {
+            if self.starts_with(b"<!--") {
+                // the dashes of `<!--` count towards the end of the comment, so `<!-->` is a complete comment
+                self.pos += 2;
+                self.skip_past(b"-->");
+                continue;
+            } else if self.starts_with(b"<meta")
+                && self
+                    .bytes
+                    .get(self.pos + 5)
+                    .is_some_and(|x| is_whitespace(*x) || *x == b'/')
+            {
+                self.pos += 5;
+                if let Some(encoding) = self.meta() {
+                    return Some(encoding);
+                }
+            } else if self.starts_with(b"<")
+                && (self
+                    .bytes
+                    .get(self.pos + 1)
+                    .is_some_and(u8::is_ascii_alphabetic)
+                    || (self.bytes.get(self.pos + 1) == Some(&b'/')
+                        && self
+                            .bytes
+                            .get(self.pos + 2)
+                            .is_some_and(u8::is_ascii_alphabetic)))
+            {
+                // Other tags are skipped along with their attributes, so `<meta>` inside of an attribute value
+                // isn't mistaken for a tag
+                self.skip_while(|x| !is_whitespace(x) && x != b'>');
+                while self.attribute().is_some() {}
+            } else if self.starts_with(b"<!") || self.starts_with(b"</") || self.starts_with(b"<?")
+            {
+                self.skip_past(b">");
+                continue;
+            }
+            self.pos += 1;
+        }
+        None
+    }
+}
+
+/// Finds the encoding in the `content` attribute of a `<meta http-equiv="Content-Type">` tag, such as
+/// `text/html; charset=windows-1252`. The value is already lowercase.
+fn charset_from_content(content: &[u8]) -> Option<Option<&'static Encoding>> {
+    let mut pos = 0;
+    loop {
+        pos += content[pos..].windows(7).position(|x| x == b"charset")? + 7;
+        while content.get(pos).is_some_and(|x| is_whitespace(*x)) {
+            pos += 1;
+        }
+        if content.get(pos) == Some(&b'=') {
+            break;
+        }
+    }
+    pos += 1;
+    while content.get(pos).is_some_and(|x| is_whitespace(*x)) {
+        pos += 1;
+    }
+
+    let value = match content.get(pos)? {
+        quote @ (b'"' | b'\'') => {
+            let len = content[pos + 1..].iter().position(|x| x == quote)?;
+            &content[pos + 1..pos + 1 + len]
+        }
+        _ => {
+            let rest = &content[pos..];
+            let len = rest
+                .iter()
+                .position(|x| is_whitespace(*x) || *x == b';')
+                .unwrap_or(rest.len());
+            &rest[..len]
+        }
+    };
+    Some(Encoding::for_label(value))
+}
+
+/// Determines the encoding of an HTML document from its byte order mark, or from a `<meta charset>` or
+/// `<meta http-equiv="Content-Type">` tag near its start, following the encoding sniffing algorithm of the HTML
+/// standard. Returns `None` if neither is found, in which case a default encoding should be used.
+pub fn sniff_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return Some(encoding);
+    }
+    let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+    Prescan { bytes, pos: 0 }.run()
+}
+
+/// Returns the attribute of a `<meta>` tag that has to change for it to declare `encoding`, along with its new
+/// value, or `None` if it already does (or doesn't declare an encoding at all).
+fn meta_charset_change(
+    attrs: &Attributes,
+    encoding: &'static Encoding,
+) -> Option<(&'static str, String)> {
+    if let Some(charset) = attrs.get("charset") {
+        (Encoding::for_label(charset.trim().as_bytes()) != Some(encoding))
+            .then(|| ("charset", encoding.name().to_string()))
+    } else if attrs
+        .get("http-equiv")
+        .is_some_and(|x| x.eq_ignore_ascii_case("content-type"))
+    {
+        let content = attrs.get("content")?.to_ascii_lowercase();
+        if !content.contains("charset") {
+            return None;
+        }
+        (charset_from_content(content.as_bytes()) != Some(Some(encoding)))
+            .then(|| ("content", format!("text/html; charset={}", encoding.name())))
+    } else {
+        None
+    }
+}
+
+/// Returns a copy of a node whose `<meta>` tags declare the encoding it is being serialized in, or `None` if they
+/// already do. The node itself is left unchanged.
+pub fn with_meta_charset(node: &NodeRef, encoding: &'static Encoding) -> Option<NodeRef> {
+    const SELECTOR: &str = "meta[charset], meta[http-equiv]";
+    let outdated = node
+        .select(SELECTOR)
+        .ok()?
+        .any(|meta| meta_charset_change(&meta.attributes.borrow(), encoding).is_some());
+    if !outdated {
+        return None;
+    }
+
+    let clone = clone_node(node);
+    for meta in clone.select(SELECTOR).ok()? {
+        let mut attrs = meta.attributes.borrow_mut();
+        if let Some((name, value)) = meta_charset_change(&attrs, encoding) {
+            attrs.insert(name, value);
+        }
+    }
+    Some(clone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1251};
+    use kuchikiki::traits::TendrilSink;
+
+    #[test]
+    fn byte_order_marks() {
+        assert_eq!(sniff_encoding(b"\xEF\xBB\xBF<meta charset=sjis>"), Some(UTF_8));
+        assert_eq!(sniff_encoding(b"\xFF\xFEa\0"), Some(UTF_16LE));
+        assert_eq!(sniff_encoding(b"\xFE\xFF\0a"), Some(UTF_16BE));
+    }
+
+    #[test]
+    fn meta_charset() {
+        assert_eq!(sniff_encoding(b"<meta charset=\"Shift_JIS\">"), Some(SHIFT_JIS));
+        assert_eq!(sniff_encoding(b"<META CHARSET=windows-1251 />"), Some(WINDOWS_1251));
+        assert_eq!(sniff_encoding(b"<meta charset=utf-16le>"), Some(UTF_8));
+        assert_eq!(sniff_encoding(b"<meta charset=x-user-defined>"), Some(WINDOWS_1252));
+        assert_eq!(sniff_encoding(b"<meta charset=nonsense>"), None);
+        assert_eq!(sniff_encoding(b"<p>no declaration</p>"), None);
+    }
+
+    #[test]
+    fn meta_http_equiv() {
+        let html = b"<meta http-equiv=Content-Type content=\"text/html; charset='sjis'\">";
+        assert_eq!(sniff_encoding(html), Some(SHIFT_JIS));
+        // without the pragma, the content attribute is ignored
+        assert_eq!(sniff_encoding(b"<meta content=\"text/html; charset=sjis\">"), None);
+        assert_eq!(
+            sniff_encoding(b"<meta content=\"charset = sjis\" http-equiv=content-type>"),
+            Some(SHIFT_JIS)
+        );
+    }
+
+    #[test]
+    fn prescan_skips_comments_and_attributes() {
+        assert_eq!(
+            sniff_encoding(b"<!-- <meta charset=sjis> --><meta charset=utf-8>"),
+            Some(UTF_8)
+        );
+        assert_eq!(sniff_encoding(b"<!--><meta charset=sjis>"), Some(SHIFT_JIS));
+        assert_eq!(
+            sniff_encoding(b"<div title='<meta charset=sjis>'><meta charset=utf-8>"),
+            Some(UTF_8)
+        );
+        assert_eq!(sniff_encoding(b"<meta name=a charset=sjis charset=utf-8>"), Some(SHIFT_JIS));
+    }
+
+    #[test]
+    fn prescan_is_limited() {
+        let mut html = vec![b' '; PRESCAN_LENGTH];
+        html.extend_from_slice(b"<meta charset=sjis>");
+        assert_eq!(sniff_encoding(&html), None);
+    }
+
+    #[test]
+    fn meta_charset_is_updated_on_a_copy() {
+        let html =
+            "<meta charset=utf-8><meta http-equiv=content-type content='text/html; charset=utf-8'>";
+        let document = kuchikiki::parse_html().one(html);
+        assert!(with_meta_charset(&document, UTF_8).is_none());
+
+        let updated = with_meta_charset(&document, SHIFT_JIS).unwrap();
+        let metas: Vec<_> = updated.select("meta").unwrap().collect();
+        assert_eq!(metas[0].attributes.borrow().get("charset"), Some("Shift_JIS"));
+        assert_eq!(
+            metas[1].attributes.borrow().get("content"),
+            Some("text/html; charset=Shift_JIS")
+        );
+        let original = document.select_first("meta").unwrap();
+        assert_eq!(original.attributes.borrow().get("charset"), Some("utf-8"));
+    }
+}
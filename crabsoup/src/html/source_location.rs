@@ -0,0 +1,272 @@
+use html5ever::{
+    interface::{ElementFlags, NextParserState, NodeOrText, QuirksMode, TreeSink},
+    namespace_url, ns,
+    tendril::{StrTendril, TendrilSink},
+    Attribute, ExpandedName, LocalName, QualName,
+};
+use kuchikiki::{Node, NodeRef, Sink};
+use std::{
+    borrow::Cow,
+    rc::{Rc, Weak},
+};
+
+use crate::wyhash::WyHashMap;
+
+#[derive(Clone, Debug)]
+pub struct SourceLocation {
+    pub file: Rc<str>,
+    pub line: u64,
+    pub column: u64,
+}
+
+/// The source locations of parsed elements.
+///
+/// Nodes are looked up by address, and the weak reference makes sure that a node that happens to be allocated at
+/// the same address as a freed one isn't given its location.
+#[derive(Default)]
+pub struct SourceLocations {
+    map: WyHashMap<usize, (Weak<Node>, SourceLocation)>,
+    pruned_len: usize,
+}
+impl SourceLocations {
+    pub fn get(&self, node: &NodeRef) -> Option<&SourceLocation> {
+        match self.map.get(&(Rc::as_ptr(&node.0) as usize)) {
+            Some((weak, location)) if weak.as_ptr() == Rc::as_ptr(&node.0) => Some(location),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, node: &NodeRef, location: SourceLocation) {
+        self.map
+            .insert(Rc::as_ptr(&node.0) as usize, (Rc::downgrade(&node.0), location));
+
+        // Forget the locations of freed nodes whenever the table has doubled in size since it was last pruned
+        if self.map.len() >= (self.pruned_len * 2).max(1024) {
+            self.map.retain(|_, (weak, _)| weak.strong_count() > 0);
+            self.pruned_len = self.map.len();
+        }
+    }
+
+    /// Copies the locations of a tree to a clone of it, such as a template that was cloned for a page.
+    pub fn copy_to_clone(&mut self, original: &NodeRef, clone: &NodeRef) {
+        if self.map.is_empty() {
+            return;
+        }
+        for (original, clone) in original
+            .inclusive_descendants()
+            .zip(clone.inclusive_descendants())
+        {
+            if let Some(location) = self.get(&original).cloned() {
+                self.insert(&clone, location);
+            }
+        }
+    }
+}
+
+/// A tree sink that builds a tree with kuchikiki's sink, and finds where each element starts in the source text.
+///
+/// html5ever only tells the sink which line it is on, so the column is found by searching for the start tag, from
+/// the line of the previous token or the last tag that was found (whichever is later) up to the end of the current
+/// line. Elements that are implied by the tree builder (such as a missing `<body>`) aren't found this way, and are
+/// given no location.
+struct LocationTreeSink<'a> {
+    inner: Sink,
+    source: &'a str,
+    line_starts: Vec<usize>,
+    current_line: u64,
+    line_start: usize,
+    cursor: usize,
+    locations: Vec<(NodeRef, u64, u64)>,
+}
+impl LocationTreeSink<'_> {
+    fn find_start_tag(&mut self, name: &str) -> Option<usize> {
+        let line_end = self
+            .line_starts
+            .get(self.current_line as usize)
+            .copied()
+            .unwrap_or(self.source.len());
+        let bytes = self.source.as_bytes();
+        let mut pos = self.cursor.max(self.line_start);
+        while pos < line_end {
+            let start = pos + self.source[pos..line_end].find('<')?;
+            let name_end = start + 1 + name.len();
+            let is_match = bytes
+                .get(start + 1..name_end)
+                .is_some_and(|x| x.eq_ignore_ascii_case(name.as_bytes()))
+                && bytes
+                    .get(name_end)
+                    .is_none_or(|x| x.is_ascii_whitespace() || *x == b'/' || *x == b'>');
+            if is_match {
+                return Some(start);
+            }
+            pos = start + 1;
+        }
+        None
+    }
+
+    fn record(&mut self, node: &NodeRef, name: &LocalName) {
+        if let Some(start) = self.find_start_tag(name) {
+            self.cursor = start + 1;
+            let line = self.line_starts.partition_point(|x| *x <= start);
+            let column = self.source[self.line_starts[line - 1]..start]
+                .chars()
+                .count()
+                + 1;
+            self.locations
+                .push((node.clone(), line as u64, column as u64));
+        }
+    }
+}
+
+impl TreeSink for LocationTreeSink<'_> {
+    type Handle = NodeRef;
+    type Output = (NodeRef, Vec<(NodeRef, u64, u64)>);
+
+    fn finish(self) -> Self::Output {
+        (self.inner.finish(), self.locations)
+    }
+
+    fn parse_error(&mut self, message: Cow<'static, str>) {
+        self.inner.parse_error(message)
+    }
+
+    fn set_current_line(&mut self, line: u64) {
+        // The tag that caused the line change can start no earlier than the line the previous token ended on
+        self.line_start = self.line_starts[self.current_line as usize - 1];
+        self.current_line = line;
+    }
+
+    fn get_document(&mut self) -> Self::Handle {
+        self.inner.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.inner.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        let local = name.local.clone();
+        let node = self.inner.create_element(name, attrs, flags);
+        self.record(&node, &local);
+        node
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.inner.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
+        self.inner.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.inner.append(parent, child)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        self.inner
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.inner
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Self::Handle) {
+        self.inner.mark_script_already_started(node)
+    }
+
+    fn pop(&mut self, node: &Self::Handle) {
+        self.inner.pop(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        self.inner.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        self.inner.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.inner.set_quirks_mode(mode)
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.inner.append_before_sibling(sibling, child)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        self.inner.add_attrs_if_missing(target, attrs)
+    }
+
+    fn associate_with_form(
+        &mut self,
+        target: &Self::Handle,
+        form: &Self::Handle,
+        nodes: (&Self::Handle, Option<&Self::Handle>),
+    ) {
+        self.inner.associate_with_form(target, form, nodes)
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.inner.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        self.inner.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Self::Handle) -> bool {
+        self.inner
+            .is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn complete_script(&mut self, node: &Self::Handle) -> NextParserState {
+        self.inner.complete_script(node)
+    }
+}
+
+/// Parses a document, or a fragment in the context of `fragment_root`, and returns the line and column that each
+/// element starts on.
+pub fn parse_with_locations(
+    source: &str,
+    fragment_root: Option<&str>,
+) -> (NodeRef, Vec<(NodeRef, u64, u64)>) {
+    let line_starts = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let sink = LocationTreeSink {
+        inner: crate::html::new_sink(),
+        source,
+        line_starts,
+        // html5ever only reports line changes, so the sink must start on the first line
+        current_line: 1,
+        line_start: 0,
+        cursor: 0,
+        locations: Vec::new(),
+    };
+    match fragment_root {
+        None => html5ever::parse_document(sink, Default::default()).one(source),
+        Some(root) => {
+            let root = QualName::new(None, ns!(html), LocalName::from(root));
+            html5ever::parse_fragment(sink, Default::default(), root, vec![]).one(source)
+        }
+    }
+}
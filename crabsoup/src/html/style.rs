@@ -0,0 +1,251 @@
+/// A declaration in a `style` attribute, such as `color: red !important`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    /// The name of the property, in lowercase unless it is a custom property such as `--main-color`.
+    pub name: String,
+    pub value: String,
+    pub important: bool,
+}
+
+/// Finds the first `delimiter` in CSS text that isn't inside of a string, comment, escape or brackets.
+fn find_top_level(text: &str, delimiter: char) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    let mut depth = 0usize;
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '"' | '\'' => {
+                while let Some((_, x)) = chars.next() {
+                    if x == '\\' {
+                        chars.next();
+                    } else if x == ch {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|(_, x)| *x == '*') => {
+                chars.next();
+                let mut prev = ' ';
+                for (_, x) in chars.by_ref() {
+                    if prev == '*' && x == '/' {
+                        break;
+                    }
+                    prev = x;
+                }
+            }
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if ch == delimiter && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Replaces the comments in CSS text with spaces, leaving strings alone.
+fn strip_comments(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                result.push(ch);
+                result.extend(chars.next());
+            }
+            '"' | '\'' => {
+                result.push(ch);
+                while let Some(x) = chars.next() {
+                    result.push(x);
+                    if x == '\\' {
+                        result.extend(chars.next());
+                    } else if x == ch {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for x in chars.by_ref() {
+                    if prev == '*' && x == '/' {
+                        break;
+                    }
+                    prev = x;
+                }
+                result.push(' ');
+            }
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+/// Splits a `!important` at the end of a value off of it.
+fn split_important(value: &str) -> (&str, bool) {
+    let trimmed = value.trim_end();
+    let len = trimmed.len();
+    if len >= 9
+        && trimmed.is_char_boundary(len - 9)
+        && trimmed[len - 9..].eq_ignore_ascii_case("important")
+    {
+        let before = trimmed[..len - 9].trim_end();
+        if let Some(value) = before.strip_suffix('!') {
+            return (value.trim_end(), true);
+        }
+    }
+    (trimmed, false)
+}
+
+/// Trims a property name, and lowercases it unless it is a custom property.
+pub fn normalize_property(name: &str) -> String {
+    let name = name.trim();
+    if name.starts_with("--") {
+        name.to_string()
+    } else {
+        name.to_ascii_lowercase()
+    }
+}
+
+fn parse_declaration(text: &str) -> Option<Declaration> {
+    let colon = find_top_level(text, ':')?;
+    let name = normalize_property(&strip_comments(&text[..colon]));
+    if name.is_empty() {
+        return None;
+    }
+
+    let value = strip_comments(&text[colon + 1..]);
+    let (value, important) = split_important(value.trim());
+    Some(Declaration { name, value: value.to_string(), important })
+}
+
+/// Parses the declarations of a `style` attribute in order. Declarations without a property name are skipped.
+pub fn parse_style(text: &str) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut rest = text;
+    loop {
+        let end = find_top_level(rest, ';').unwrap_or(rest.len());
+        if let Some(declaration) = parse_declaration(&rest[..end]) {
+            declarations.push(declaration);
+        }
+        if end == rest.len() {
+            break;
+        }
+        rest = &rest[end + 1..];
+    }
+    declarations
+}
+
+/// Parses a single declaration for the given property, as given to `HTML.set_style`. Returns `None` if the value
+/// would change the declarations around it, such as a value containing a `;` or an unterminated string.
+pub fn parse_single_declaration(name: &str, value: &str) -> Option<Declaration> {
+    let text = format!("{name}: {value}");
+    // a `;` added at the end must be the first one outside of strings, comments and brackets
+    if find_top_level(&format!("{text};"), ';') != Some(text.len()) {
+        return None;
+    }
+    let declaration = parse_declaration(&text)?;
+    (declaration.name == normalize_property(name)).then_some(declaration)
+}
+
+/// Returns the value of each property that applies, where later declarations of a property replace earlier ones
+/// unless only the earlier one is `!important`.
+pub fn effective_style(declarations: &[Declaration]) -> Vec<&Declaration> {
+    let mut result: Vec<&Declaration> = Vec::new();
+    for declaration in declarations {
+        match result.iter_mut().find(|x| x.name == declaration.name) {
+            Some(existing) if existing.important && !declaration.important => {}
+            Some(existing) => *existing = declaration,
+            None => result.push(declaration),
+        }
+    }
+    result
+}
+
+pub fn serialize_style(declarations: &[Declaration]) -> String {
+    let mut result = String::new();
+    for declaration in declarations {
+        if !result.is_empty() {
+            result.push_str("; ");
+        }
+        result.push_str(&declaration.name);
+        result.push_str(": ");
+        result.push_str(&declaration.value);
+        if declaration.important {
+            result.push_str(" !important");
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declaration(name: &str, value: &str, important: bool) -> Declaration {
+        Declaration { name: name.to_string(), value: value.to_string(), important }
+    }
+
+    #[test]
+    fn declarations_are_parsed_in_order() {
+        assert_eq!(
+            parse_style(" COLOR : Red ; margin:0 !IMPORTANT;;--Main-Color: blue; : x; junk"),
+            [
+                declaration("color", "Red", false),
+                declaration("margin", "0", true),
+                declaration("--Main-Color", "blue", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings_comments_and_brackets_are_kept_together() {
+        assert_eq!(
+            parse_style("background: url(\"a;b.png\"); content: 'x;y' /* ; */; --v: {a: b; c}"),
+            [
+                declaration("background", "url(\"a;b.png\")", false),
+                declaration("content", "'x;y'", false),
+                declaration("--v", "{a: b; c}", false),
+            ]
+        );
+        assert_eq!(
+            parse_style("/* color: red; */ width: 1px"),
+            [declaration("width", "1px", false)]
+        );
+        assert_eq!(parse_style("content: '\\';'"), [declaration("content", "'\\';'", false)]);
+    }
+
+    #[test]
+    fn single_declarations_are_validated() {
+        assert_eq!(
+            parse_single_declaration("Color", "red !important"),
+            Some(declaration("color", "red", true))
+        );
+        assert_eq!(parse_single_declaration("color", "red; display: none"), None);
+        assert_eq!(parse_single_declaration("content", "'unterminated"), None);
+        assert_eq!(parse_single_declaration("color", "red /* ;"), None);
+        assert_eq!(parse_single_declaration("a: b", "c"), None);
+        assert_eq!(
+            parse_single_declaration("content", "';'"),
+            Some(declaration("content", "';'", false))
+        );
+    }
+
+    #[test]
+    fn important_declarations_take_precedence() {
+        let declarations =
+            parse_style("color: red !important; margin: 0; color: blue; margin: 1px");
+        assert_eq!(
+            effective_style(&declarations),
+            [&declaration("color", "red", true), &declaration("margin", "1px", false)]
+        );
+    }
+
+    #[test]
+    fn serialization() {
+        let declarations = parse_style("color:red;margin : 0 ! important");
+        assert_eq!(serialize_style(&declarations), "color: red; margin: 0 !important");
+        assert_eq!(serialize_style(&[]), "");
+    }
+}
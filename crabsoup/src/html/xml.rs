@@ -0,0 +1,388 @@
+use crate::html::minify::is_void;
+use html5ever::{
+    interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink},
+    namespace_url, ns,
+    tendril::{StrTendril, TendrilSink},
+    Attribute, ExpandedName, Namespace, Prefix, QualName,
+};
+use kuchikiki::{traits::NodeIterator, NodeData, NodeRef};
+use std::borrow::Cow;
+use xml5ever::driver::XmlParseOpts;
+
+/// A tree sink that builds an XML document out of the same nodes as the HTML parser, so it can be used with the
+/// rest of the `HTML` library. Everything but template handling is delegated to kuchikiki's sink.
+struct XmlTreeSink {
+    inner: kuchikiki::Sink,
+}
+
+impl TreeSink for XmlTreeSink {
+    type Handle = NodeRef;
+    type Output = NodeRef;
+
+    fn finish(self) -> Self::Output {
+        self.inner.finish()
+    }
+
+    fn parse_error(&mut self, _: Cow<'static, str>) {}
+
+    fn get_document(&mut self) -> Self::Handle {
+        self.inner.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Self::Handle) -> ExpandedName<'a> {
+        self.inner.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Self::Handle {
+        self.inner.create_element(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Self::Handle {
+        self.inner.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Self::Handle {
+        self.inner.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.inner.append(parent, child)
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Self::Handle,
+        prev_element: &Self::Handle,
+        child: NodeOrText<Self::Handle>,
+    ) {
+        self.inner
+            .append_based_on_parent_node(element, prev_element, child)
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.inner
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        // XML has no templates, so their contents are their children
+        target.clone()
+    }
+
+    fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
+        self.inner.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, _: QuirksMode) {}
+
+    fn append_before_sibling(&mut self, sibling: &Self::Handle, child: NodeOrText<Self::Handle>) {
+        self.inner.append_before_sibling(sibling, child)
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Self::Handle, attrs: Vec<Attribute>) {
+        self.inner.add_attrs_if_missing(target, attrs)
+    }
+
+    fn remove_from_parent(&mut self, target: &Self::Handle) {
+        self.inner.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Self::Handle, new_parent: &Self::Handle) {
+        self.inner.reparent_children(node, new_parent)
+    }
+}
+
+/// Parses an XML document, such as a feed or an SVG image.
+pub fn parse_xml(text: &str) -> NodeRef {
+    let sink = XmlTreeSink { inner: crate::html::new_sink() };
+    xml5ever::driver::parse_document(sink, XmlParseOpts::default()).one(text)
+}
+
+/// Splits a qualified name such as `atom:link` into its prefix and local name.
+pub fn split_qual_name(name: &str) -> (Option<Prefix>, &str) {
+    match name.split_once(':') {
+        Some((prefix, local)) => (Some(Prefix::from(prefix)), local),
+        None => (None, name),
+    }
+}
+
+/// The namespace declarations in scope for an element, as a list of prefixes and their namespaces.
+type Scope = Vec<(Option<Prefix>, Namespace)>;
+
+fn lookup_prefix<'a>(scope: &'a Scope, prefix: &Option<Prefix>) -> Option<&'a Namespace> {
+    scope
+        .iter()
+        .rev()
+        .find(|(x, _)| x == prefix)
+        .map(|(_, ns)| ns)
+}
+
+/// Finds the prefixes used in a subtree, so they can be declared once on its root element.
+fn used_prefixes(node: &NodeRef) -> Scope {
+    let mut prefixes: Scope = Vec::new();
+    let mut add = |prefix: &Option<Prefix>, ns: &Namespace| {
+        if prefix.is_some() && *ns != ns!(xmlns) && !prefixes.iter().any(|(x, _)| x == prefix) {
+            prefixes.push((prefix.clone(), ns.clone()));
+        }
+    };
+    for elem in node.inclusive_descendants().elements() {
+        let name = elem.name.borrow();
+        add(&name.prefix, &name.ns);
+        for (attr_name, attr) in &elem.attributes.borrow().map {
+            add(&attr.prefix, &attr_name.ns);
+        }
+    }
+    prefixes
+}
+
+struct XmlSerializer {
+    out: String,
+}
+impl XmlSerializer {
+    fn push_escaped(&mut self, text: &str, attribute: bool) {
+        for ch in text.chars() {
+            match ch {
+                '&' => self.out.push_str("&amp;"),
+                '<' => self.out.push_str("&lt;"),
+                '>' if !attribute => self.out.push_str("&gt;"),
+                '"' if attribute => self.out.push_str("&quot;"),
+                _ => self.out.push(ch),
+            }
+        }
+    }
+
+    fn push_name(&mut self, prefix: &Option<Prefix>, local: &str) {
+        if let Some(prefix) = prefix {
+            self.out.push_str(prefix);
+            self.out.push(':');
+        }
+        self.out.push_str(local);
+    }
+
+    /// Declares the namespace of an element or attribute name, if it isn't already in scope.
+    fn declare(&mut self, scope: &mut Scope, prefix: &Option<Prefix>, ns: &Namespace) {
+        let is_reserved = matches!(prefix.as_deref(), Some("xml" | "xmlns"));
+        if is_reserved || lookup_prefix(scope, prefix).unwrap_or(&ns!()) == ns {
+            return;
+        }
+        self.out.push_str(" xmlns");
+        if let Some(prefix) = prefix {
+            self.out.push(':');
+            self.out.push_str(prefix);
+        }
+        self.out.push_str("=\"");
+        self.push_escaped(ns, true);
+        self.out.push('"');
+        scope.push((prefix.clone(), ns.clone()));
+    }
+
+    fn element(&mut self, node: &NodeRef, scope: &Scope, top_level: bool) {
+        let elem = node.as_element().unwrap();
+        let name = elem.name.borrow();
+        let attrs = elem.attributes.borrow();
+        let mut scope = scope.clone();
+
+        // Namespace declarations that are already attributes of the element come into scope first
+        for (attr_name, attr) in &attrs.map {
+            if attr_name.ns == ns!(xmlns) {
+                let prefix = (attr.prefix.is_some()).then(|| Prefix::from(&*attr_name.local));
+                scope.push((prefix, Namespace::from(&*attr.value)));
+            }
+        }
+
+        self.out.push('<');
+        self.push_name(&name.prefix, &name.local);
+        self.declare(&mut scope, &name.prefix, &name.ns);
+        if top_level {
+            for (prefix, ns) in used_prefixes(node) {
+                self.declare(&mut scope, &prefix, &ns);
+            }
+        }
+        for (attr_name, attr) in &attrs.map {
+            if !attr_name.ns.is_empty() && attr_name.ns != ns!(xmlns) && attr.prefix.is_some() {
+                self.declare(&mut scope, &attr.prefix, &attr_name.ns);
+            }
+            self.out.push(' ');
+            self.push_name(&attr.prefix, &attr_name.local);
+            self.out.push_str("=\"");
+            self.push_escaped(&attr.value, true);
+            self.out.push('"');
+        }
+
+        // HTML elements that aren't void keep their end tags, so XHTML can still be read by HTML parsers
+        let self_closing = if name.ns == ns!(html) {
+            is_void(&name.local)
+        } else {
+            node.first_child().is_none()
+        };
+        if self_closing {
+            self.out.push_str("/>");
+            return;
+        }
+        self.out.push('>');
+        for child in node.children() {
+            self.node(&child, &scope, false);
+        }
+        self.out.push_str("</");
+        self.push_name(&name.prefix, &name.local);
+        self.out.push('>');
+    }
+
+    fn node(&mut self, node: &NodeRef, scope: &Scope, top_level: bool) {
+        match node.data() {
+            NodeData::Element(_) => self.element(node, scope, top_level),
+            NodeData::Text(text) => self.push_escaped(&text.borrow(), false),
+            NodeData::Comment(text) => {
+                self.out.push_str("<!--");
+                self.out.push_str(&text.borrow());
+                self.out.push_str("-->");
+            }
+            NodeData::ProcessingInstruction(contents) => {
+                let contents = contents.borrow();
+                self.out.push_str("<?");
+                self.out.push_str(&contents.0);
+                if !contents.1.is_empty() {
+                    self.out.push(' ');
+                    self.out.push_str(&contents.1);
+                }
+                self.out.push_str("?>");
+            }
+            NodeData::Doctype(doctype) => {
+                self.out.push_str("<!DOCTYPE ");
+                self.out.push_str(&doctype.name);
+                if !doctype.public_id.is_empty() {
+                    self.out.push_str(" PUBLIC \"");
+                    self.out.push_str(&doctype.public_id);
+                    self.out.push_str("\" \"");
+                    self.out.push_str(&doctype.system_id);
+                    self.out.push('"');
+                } else if !doctype.system_id.is_empty() {
+                    self.out.push_str(" SYSTEM \"");
+                    self.out.push_str(&doctype.system_id);
+                    self.out.push('"');
+                }
+                self.out.push('>');
+            }
+            // Whitespace outside of the root element is insignificant, so it's replaced with line breaks
+            NodeData::Document(_) => {
+                let mut previous_is_text = true;
+                for child in node.children() {
+                    let is_text = match child.as_text() {
+                        Some(text) if text.borrow().trim().is_empty() => continue,
+                        Some(_) => true,
+                        None => false,
+                    };
+                    if !previous_is_text && !is_text {
+                        self.out.push('\n');
+                    }
+                    self.node(&child, scope, true);
+                    previous_is_text = is_text;
+                }
+            }
+            NodeData::DocumentFragment => {
+                for child in node.children() {
+                    self.node(&child, scope, true);
+                }
+            }
+        }
+    }
+}
+
+fn has_xml_declaration(document: &NodeRef) -> bool {
+    let first = document.children().find(|x| x.as_text().is_none());
+    matches!(first.as_ref().map(|x| x.data()), Some(NodeData::ProcessingInstruction(pi)) if pi.borrow().0 == "xml")
+}
+
+/// Serializes a node as XML, declaring namespaces where they are needed and using self-closing tags for empty
+/// elements. Documents start with an XML declaration, unless they already have one.
+pub fn to_xml_string(node: &NodeRef) -> String {
+    let mut serializer = XmlSerializer { out: String::new() };
+    if matches!(node.data(), NodeData::Document(_)) && !has_xml_declaration(node) {
+        serializer
+            .out
+            .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    }
+    serializer.node(node, &Vec::new(), true);
+    serializer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html5ever::LocalName;
+
+    fn element(ns: Namespace, name: &str) -> NodeRef {
+        let (prefix, local) = split_qual_name(name);
+        NodeRef::new_element(QualName::new(prefix, ns, LocalName::from(local)), vec![])
+    }
+
+    #[test]
+    fn parsed_elements_keep_namespaces() {
+        let document = parse_xml(
+            r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"><url><loc>a</loc></url></urlset>"#,
+        );
+        let loc = document.select_first("loc").unwrap();
+        let name = loc.name.borrow();
+        assert_eq!(&*name.ns, "http://www.sitemaps.org/schemas/sitemap/0.9");
+        assert_eq!(&*name.local, "loc");
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/"><title>A &amp; B</title><media:thumbnail url="a.png"/><!-- x --></feed>"#;
+        assert_eq!(
+            to_xml_string(&parse_xml(source)),
+            format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{source}")
+        );
+        let declared = "<?xml version=\"1.0\"?>\n<a/>";
+        assert_eq!(to_xml_string(&parse_xml(declared)), declared);
+    }
+
+    #[test]
+    fn namespaces_are_declared_where_needed() {
+        let rss = element(ns!(), "rss");
+        let item = element(ns!(), "item");
+        item.append(element(Namespace::from("http://search.yahoo.com/mrss/"), "media:thumbnail"));
+        rss.append(item);
+        assert_eq!(
+            to_xml_string(&rss),
+            r#"<rss xmlns:media="http://search.yahoo.com/mrss/"><item><media:thumbnail/></item></rss>"#
+        );
+
+        // Elements created for HTML keep their namespace inside of other XML
+        let rss = element(ns!(), "rss");
+        rss.append(element(ns!(html), "item"));
+        assert_eq!(
+            to_xml_string(&rss),
+            r#"<rss><item xmlns="http://www.w3.org/1999/xhtml"></item></rss>"#
+        );
+    }
+
+    #[test]
+    fn html_elements_keep_end_tags() {
+        let div = element(ns!(html), "div");
+        div.append(element(ns!(html), "br"));
+        div.append(element(ns!(html), "script"));
+        assert_eq!(
+            to_xml_string(&div),
+            r#"<div xmlns="http://www.w3.org/1999/xhtml"><br/><script></script></div>"#
+        );
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        let root = element(ns!(), "a");
+        root.append(NodeRef::new_text("<b> & \"c\""));
+        assert_eq!(to_xml_string(&root), "<a>&lt;b&gt; &amp; \"c\"</a>");
+    }
+}
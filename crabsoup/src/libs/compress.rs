@@ -0,0 +1,144 @@
+use crate::{libs::process::ASYNC_EXECUTOR, paths::lstr_to_system_path};
+use flate2::{write::GzEncoder, Compression};
+use mlua::{prelude::LuaString, Error, Lua, Result, Table};
+use sha2::{Digest, Sha256};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[derive(Copy, Clone, Debug)]
+enum Format {
+    Gzip,
+    Brotli,
+}
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Gzip => "gz",
+            Format::Brotli => "br",
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Format::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Format::Brotli => {
+                let mut out = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 11,
+                    lgwin: 22,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+fn sibling_path(path: &Path, format: Format) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(format.extension());
+    PathBuf::from(name)
+}
+
+/// Writes compressed siblings of a file, returning the number of files that had to be compressed.
+///
+/// Compressed data is stored in the cache directory by content hash, so files that didn't change since
+/// the last build are copied from there instead.
+fn precompress_file(
+    path: &Path,
+    formats: &[Format],
+    cache_dir: Option<&Path>,
+) -> anyhow::Result<u32> {
+    let data = std::fs::read(path)?;
+    let hash = format!("{:x}", Sha256::digest(&data));
+
+    let mut compressed_count = 0;
+    for &format in formats {
+        let cache_path = cache_dir.map(|dir| dir.join(format!("{hash}.{}", format.extension())));
+        let compressed = match &cache_path {
+            Some(cache_path) if cache_path.exists() => std::fs::read(cache_path)?,
+            _ => {
+                let compressed = format.compress(&data)?;
+                if let Some(cache_path) = &cache_path {
+                    // identical files may be compressed concurrently, so the cache entry is written atomically
+                    let thread_id = std::thread::current().id();
+                    let temp_path = cache_path.with_extension(format!("tmp-{thread_id:?}"));
+                    std::fs::write(&temp_path, &compressed)?;
+                    std::fs::rename(&temp_path, cache_path)?;
+                }
+                compressed_count += 1;
+                compressed
+            }
+        };
+
+        // serving a compressed file that is larger than the original is pointless
+        if compressed.len() < data.len() {
+            std::fs::write(sibling_path(path, format), compressed)?;
+        }
+    }
+    Ok(compressed_count)
+}
+
+pub fn create_compress_table(lua: &Lua) -> Result<Table> {
+    let table = lua.create_table()?;
+
+    table.raw_set(
+        "precompress",
+        lua.create_function(|_, (paths, options): (Vec<LuaString>, Option<Table>)| {
+            let mut formats = Vec::new();
+            let mut cache_dir = None;
+            if let Some(options) = options {
+                if options.get::<_, Option<bool>>("gzip")?.unwrap_or(true) {
+                    formats.push(Format::Gzip);
+                }
+                if options.get::<_, Option<bool>>("brotli")?.unwrap_or(true) {
+                    formats.push(Format::Brotli);
+                }
+                if let Some(dir) = options.get::<_, Option<LuaString>>("cache_dir")? {
+                    let dir = lstr_to_system_path(dir)?;
+                    std::fs::create_dir_all(&dir)?;
+                    cache_dir = Some(dir);
+                }
+            } else {
+                formats.extend([Format::Gzip, Format::Brotli]);
+            }
+
+            let mut paths_vec = Vec::new();
+            for path in paths {
+                paths_vec.push(lstr_to_system_path(path)?);
+            }
+
+            ASYNC_EXECUTOR.block_on(async move {
+                let mut handles = Vec::new();
+                for path in paths_vec {
+                    let formats = formats.clone();
+                    let cache_dir = cache_dir.clone();
+                    handles.push(ASYNC_EXECUTOR.spawn_blocking(move || {
+                        precompress_file(&path, &formats, cache_dir.as_deref()).map_err(|e| {
+                            Error::runtime(format_args!(
+                                "Could not compress {}: {e}",
+                                path.display()
+                            ))
+                        })
+                    }));
+                }
+
+                let mut compressed_count = 0;
+                for handle in handles {
+                    compressed_count += handle.await.map_err(Error::external)??;
+                }
+                Ok(compressed_count)
+            })
+        })?,
+    )?;
+
+    Ok(table)
+}
@@ -1,21 +1,31 @@
+use base64::Engine;
 use blake2::{Blake2b512, Blake2s256};
 use digest::Digest;
 use md5::Md5;
 use mlua::{
     prelude::{LuaFunction, LuaString},
-    Lua, Result, Table,
+    Error, Lua, Result, Table,
 };
 use sha1::Sha1;
-use sha2::{Sha256, Sha512};
+use sha2::{Sha256, Sha384, Sha512};
 use std::fmt::Write;
 
 fn digest_helper<D: Digest>(lua: &Lua) -> Result<LuaFunction> {
-    Ok(lua.create_function(|_, input: LuaString| {
-        let mut str = String::new();
-        for byte in D::digest(input.as_bytes()).as_slice() {
-            write!(str, "{:02x}", *byte).unwrap();
+    Ok(lua.create_function(|_, (input, format): (LuaString, Option<LuaString>)| {
+        let digest = D::digest(input.as_bytes());
+        match format.as_ref().map(|x| x.to_str()).transpose()? {
+            None | Some("hex") => {
+                let mut str = String::new();
+                for byte in digest.as_slice() {
+                    write!(str, "{:02x}", *byte).unwrap();
+                }
+                Ok(str)
+            }
+            Some("base64") => {
+                Ok(base64::engine::general_purpose::STANDARD.encode(digest.as_slice()))
+            }
+            Some(format) => Err(Error::runtime(format_args!("Unknown digest format: {format}"))),
         }
-        Ok(str)
     })?)
 }
 
@@ -25,6 +35,7 @@ pub fn create_digest_table(lua: &Lua) -> Result<Table> {
     table.raw_set("md5", digest_helper::<Md5>(lua)?)?;
     table.raw_set("sha1", digest_helper::<Sha1>(lua)?)?;
     table.raw_set("sha256", digest_helper::<Sha256>(lua)?)?;
+    table.raw_set("sha384", digest_helper::<Sha384>(lua)?)?;
     table.raw_set("sha512", digest_helper::<Sha512>(lua)?)?;
     table.raw_set("blake2s", digest_helper::<Blake2s256>(lua)?)?;
     table.raw_set("blake2b", digest_helper::<Blake2b512>(lua)?)?;
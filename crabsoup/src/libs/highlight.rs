@@ -0,0 +1,185 @@
+use crate::{paths::lstr_to_system_path, wyhash::WyHashMap};
+use mlua::{prelude::LuaString, Error, Lua, Result, Table};
+use std::sync::{Arc, LazyLock, Mutex};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{
+        append_highlighted_html_for_styled_line, css_for_theme_with_class_style, ClassStyle,
+        ClassedHTMLGenerator, IncludeBackground,
+    },
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+const DEFAULT_THEME: &str = "InspiredGitHub";
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+static THEME_CACHE: LazyLock<Mutex<WyHashMap<String, Arc<Theme>>>> =
+    LazyLock::new(Default::default);
+static CLASS_PREFIXES: LazyLock<Mutex<WyHashMap<String, &'static str>>> =
+    LazyLock::new(Default::default);
+
+fn load_theme(name: Option<LuaString>) -> Result<Arc<Theme>> {
+    let name_str = match &name {
+        Some(name) => name.to_str()?,
+        None => DEFAULT_THEME,
+    };
+    let mut theme_cache = THEME_CACHE.lock().unwrap();
+    if let Some(theme) = theme_cache.get(name_str) {
+        return Ok(theme.clone());
+    }
+
+    let theme = if let Some(theme) = THEMES.themes.get(name_str) {
+        theme.clone()
+    } else if let (Some(path), true) = (&name, name_str.ends_with(".tmTheme")) {
+        ThemeSet::get_theme(lstr_to_system_path(path.clone())?).map_err(Error::runtime)?
+    } else {
+        return Err(Error::runtime(format_args!("Unknown highlighting theme: {name_str}")));
+    };
+    let theme = Arc::new(theme);
+    theme_cache.insert(name_str.to_string(), theme.clone());
+    Ok(theme)
+}
+
+/// syntect requires class prefixes to be `'static`, so they are interned for the program's lifetime.
+fn class_style(prefix: Option<LuaString>) -> Result<ClassStyle> {
+    match prefix {
+        None => Ok(ClassStyle::Spaced),
+        Some(prefix) => {
+            let prefix = prefix.to_str()?;
+            let mut prefixes = CLASS_PREFIXES.lock().unwrap();
+            let prefix = match prefixes.get(prefix) {
+                Some(prefix) => *prefix,
+                None => {
+                    let leaked: &'static str = Box::leak(prefix.to_string().into_boxed_str());
+                    prefixes.insert(prefix.to_string(), leaked);
+                    leaked
+                }
+            };
+            Ok(ClassStyle::SpacedPrefixed { prefix })
+        }
+    }
+}
+
+fn highlight(
+    code: &str,
+    language: &str,
+    inline: bool,
+    theme: Option<LuaString>,
+    class_prefix: Option<LuaString>,
+) -> Result<Option<String>> {
+    let Some(syntax) = SYNTAXES.find_syntax_by_token(language) else {
+        return Ok(None);
+    };
+
+    if inline {
+        let theme = load_theme(theme)?;
+        let mut highlighter = HighlightLines::new(syntax, &theme);
+        let mut output = String::new();
+        for line in LinesWithEndings::from(code) {
+            let regions = highlighter
+                .highlight_line(line, &SYNTAXES)
+                .map_err(Error::runtime)?;
+            append_highlighted_html_for_styled_line(&regions, IncludeBackground::No, &mut output)
+                .map_err(Error::runtime)?;
+        }
+        Ok(Some(output))
+    } else {
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax,
+            &SYNTAXES,
+            class_style(class_prefix)?,
+        );
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .map_err(Error::runtime)?;
+        }
+        Ok(Some(generator.finalize()))
+    }
+}
+
+pub fn create_highlight_table(lua: &Lua) -> Result<Table> {
+    let table = lua.create_table()?;
+
+    table.raw_set(
+        "highlight",
+        lua.create_function(
+            |lua, (code, language, options): (LuaString, LuaString, Option<Table>)| {
+                let (inline, theme, class_prefix) = match options {
+                    Some(options) => (
+                        options.get::<_, Option<bool>>("inline")?.unwrap_or(false),
+                        options.get::<_, Option<LuaString>>("theme")?,
+                        options.get::<_, Option<LuaString>>("class_prefix")?,
+                    ),
+                    None => (false, None, None),
+                };
+                match highlight(code.to_str()?, language.to_str()?, inline, theme, class_prefix)? {
+                    Some(html) => Ok(Some(lua.create_string(html)?)),
+                    None => Ok(None),
+                }
+            },
+        )?,
+    )?;
+    table.raw_set(
+        "theme_css",
+        lua.create_function(|_, (theme, class_prefix): (Option<LuaString>, Option<LuaString>)| {
+            let theme = load_theme(theme)?;
+            css_for_theme_with_class_style(&theme, class_style(class_prefix)?)
+                .map_err(Error::runtime)
+        })?,
+    )?;
+    table.raw_set(
+        "has_language",
+        lua.create_function(|_, language: LuaString| {
+            Ok(SYNTAXES.find_syntax_by_token(language.to_str()?).is_some())
+        })?,
+    )?;
+    table.raw_set(
+        "list_themes",
+        lua.create_function(|lua, ()| {
+            let table = lua.create_table()?;
+            for name in THEMES.themes.keys() {
+                table.raw_push(name.as_str())?;
+            }
+            Ok(table)
+        })?,
+    )?;
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::extract_text::strip_tags;
+    use kuchikiki::traits::TendrilSink;
+
+    const CODE: &str = "fn main() {\n    if true {\n        println!(\"<hi>\");\n    }\n}\n";
+
+    fn highlighted_text(inline: bool) -> String {
+        let escaped = html_escape::encode_text(CODE);
+        let page = kuchikiki::parse_html().one(format!("<pre><code>{escaped}</code></pre>"));
+        let code = strip_tags(&page.select_first("code").unwrap().as_node().clone());
+        assert_eq!(code, CODE);
+
+        let html = highlight(&code, "rust", inline, None, None)
+            .unwrap()
+            .unwrap();
+        let fragment = kuchikiki::parse_html().one(format!("<pre><code>{html}</code></pre>"));
+        strip_tags(&fragment.select_first("code").unwrap().as_node().clone())
+    }
+
+    #[test]
+    fn line_breaks_and_indentation_are_kept() {
+        assert_eq!(highlighted_text(false), CODE);
+        assert_eq!(highlighted_text(true), CODE);
+    }
+
+    #[test]
+    fn unknown_languages_are_not_highlighted() {
+        assert_eq!(highlight(CODE, "no-such-language", false, None, None).unwrap(), None);
+    }
+}
@@ -1,18 +1,37 @@
 use crate::{
     html::{
         clone_node,
+        diff::{diff, Change, DiffOptions},
         extract_text::{inner_text, strip_tags},
         is_document::is_document,
+        markdown::{markdown_to_html, markdown_to_html_with_source_map, MarkdownSourceMap},
+        minify::minify,
+        parse_errors::parse_errors,
+        pretty_print::{pretty_print, PrettyPrintOptions},
+        sanitize::{sanitize, SanitizePolicy},
+        sniff_encoding::{sniff_encoding, with_meta_charset},
+        source_location::{parse_with_locations, SourceLocation, SourceLocations},
+        style::{
+            effective_style, normalize_property, parse_single_declaration, parse_style,
+            serialize_style,
+        },
+        xml::{parse_xml, split_qual_name, to_xml_string},
     },
-    wyhash::WyHashSet,
+    wyhash::{WyHashMap, WyHashSet},
 };
 use encoding_rs::{Encoding, UTF_8};
-use html5ever::{namespace_url, ns, LocalName, QualName};
+use html5ever::{namespace_url, ns, LocalName, Namespace, QualName};
 use kuchikiki::{
-    parse_fragment, parse_html, traits::TendrilSink, ElementData, NodeDataRef, NodeRef, Selectors,
+    parse_fragment, parse_html,
+    traits::{NodeIterator, TendrilSink},
+    Attribute, ElementData, ExpandedName, NodeData, NodeDataRef, NodeRef, Selectors,
 };
-use mlua::{prelude::LuaString, Error, Lua, Result, Table, UserData, UserDataFields, UserDataRef};
-use std::{borrow::Cow, cell::RefCell, io::Cursor, rc::Rc, str::Split};
+use lru::LruCache;
+use mlua::{
+    prelude::LuaString, Error, Lua, Result, Table, UserData, UserDataFields, UserDataMethods,
+    UserDataRef, Value,
+};
+use std::{borrow::Cow, cell::RefCell, io::Cursor, num::NonZeroUsize, rc::Rc, str::Split};
 use tracing::warn;
 
 fn qual_name(name: &str) -> QualName {
@@ -42,31 +61,135 @@ fn encoding_warning(lua: &Lua, encoding: &'static Encoding, encode: bool) {
         warn!("{location}: Encountered invalid {} while parsing HTML.", encoding.name());
     }
 }
+#[derive(Clone, Debug)]
+enum OutputMode {
+    Plain,
+    PrettyPrint(PrettyPrintOptions),
+    Minify,
+}
+/// Reads the options for pretty printing, with the defaults of [`PrettyPrintOptions`] for missing fields.
+fn pretty_print_options(options: Option<Table>) -> Result<PrettyPrintOptions> {
+    let mut result = PrettyPrintOptions::default();
+    if let Some(options) = options {
+        if let Some(indent) = options.get::<_, Option<usize>>("indent")? {
+            result.indent = indent;
+        }
+        if let Some(wrap) = options.get::<_, Option<usize>>("wrap")? {
+            result.wrap = wrap;
+        }
+        if let Some(wrap_attributes) = options.get::<_, Option<bool>>("wrap_attributes")? {
+            result.wrap_attributes = wrap_attributes;
+        }
+        if let Some(preserve) = options.get::<_, Option<Vec<String>>>("preserve")? {
+            result.preserve = preserve;
+        }
+    }
+    Ok(result)
+}
+/// Converts a change found by [`diff`] to a table with a `kind` field.
+fn change_to_table<'lua>(lua: &'lua Lua, change: Change) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    match change {
+        Change::Insert { path, node } => {
+            table.raw_set("kind", "insert")?;
+            table.raw_set("path", path)?;
+            table.raw_set("node", LuaNodeRef(node))?;
+        }
+        Change::Remove { path, node } => {
+            table.raw_set("kind", "remove")?;
+            table.raw_set("path", path)?;
+            table.raw_set("node", LuaNodeRef(node))?;
+        }
+        Change::Move { from, path, node } => {
+            table.raw_set("kind", "move")?;
+            table.raw_set("from", from)?;
+            table.raw_set("path", path)?;
+            table.raw_set("node", LuaNodeRef(node))?;
+        }
+        Change::Attribute { path, name, old, new } => {
+            table.raw_set("kind", "attribute")?;
+            table.raw_set("path", path)?;
+            table.raw_set("name", name)?;
+            table.raw_set("old", old)?;
+            table.raw_set("new", new)?;
+        }
+        Change::AttributeOrder { path, old, new } => {
+            table.raw_set("kind", "attribute_order")?;
+            table.raw_set("path", path)?;
+            table.raw_set("old", old)?;
+            table.raw_set("new", new)?;
+        }
+        Change::Text { path, old, new } => {
+            table.raw_set("kind", "text")?;
+            table.raw_set("path", path)?;
+            table.raw_set("old", old)?;
+            table.raw_set("new", new)?;
+        }
+    }
+    Ok(table)
+}
+/// Reads a sanitizer policy. Each field that is given replaces the default of [`SanitizePolicy`] entirely.
+fn sanitize_policy(policy: Option<Table>) -> Result<SanitizePolicy> {
+    let mut result = SanitizePolicy::default();
+    if let Some(policy) = policy {
+        if let Some(tags) = policy.get::<_, Option<Vec<String>>>("tags")? {
+            result.tags = tags.into_iter().map(|x| x.to_ascii_lowercase()).collect();
+        }
+        if let Some(remove_tags) = policy.get::<_, Option<Vec<String>>>("remove_tags")? {
+            result.remove_tags = remove_tags
+                .into_iter()
+                .map(|x| x.to_ascii_lowercase())
+                .collect();
+        }
+        if let Some(attributes) =
+            policy.get::<_, Option<WyHashMap<String, Vec<String>>>>("attributes")?
+        {
+            result.attributes = attributes
+                .into_iter()
+                .map(|(tag, attrs)| {
+                    let attrs = attrs.into_iter().map(|x| x.to_ascii_lowercase()).collect();
+                    (tag.to_ascii_lowercase(), attrs)
+                })
+                .collect();
+        }
+        if let Some(url_schemes) = policy.get::<_, Option<Vec<String>>>("url_schemes")? {
+            result.url_schemes = url_schemes
+                .into_iter()
+                .map(|x| x.to_ascii_lowercase())
+                .collect();
+        }
+        if let Some(link_rel) = policy.get::<_, Option<String>>("link_rel")? {
+            result.link_rel = link_rel;
+        }
+        if let Some(strip_comments) = policy.get::<_, Option<bool>>("strip_comments")? {
+            result.strip_comments = strip_comments;
+        }
+    }
+    Ok(result)
+}
 fn html_to_string<'lua>(
     lua: &'lua Lua,
     node: &NodeRef,
     encoding: &Option<LuaString>,
     active_encoding_ref: &Rc<RefCell<&'static Encoding>>,
-    pretty_print: bool,
+    mode: OutputMode,
 ) -> Result<LuaString<'lua>> {
     let encoding = match encoding {
         None => *active_encoding_ref.borrow(),
         Some(encoding) => decode_encoding(&encoding)?,
     };
 
+    let updated = with_meta_charset(node, encoding.output_encoding());
+    let node = updated.as_ref().unwrap_or(node);
+
     let mut data = Vec::new();
-    node.serialize(&mut Cursor::new(&mut data))?;
-    let rust_str = std::str::from_utf8(&data)?;
-    let processed = if pretty_print {
-        match crate::html::pretty_print(rust_str) {
-            Ok(value) => Cow::Owned(value),
-            Err(_) => {
-                warn!("Failed to pretty print HTML document.");
-                Cow::Borrowed(rust_str)
-            }
+    let processed = match &mode {
+        OutputMode::Plain => {
+            node.serialize(&mut Cursor::new(&mut data))?;
+            Cow::Borrowed(std::str::from_utf8(&data)?)
         }
-    } else {
-        Cow::Borrowed(rust_str)
+        OutputMode::PrettyPrint(options) => Cow::Owned(pretty_print(node, options)),
+        OutputMode::Minify => Cow::Owned(minify(node)),
     };
     let (text, encoding, errors) = encoding.encode(&processed);
     if errors {
@@ -82,6 +205,13 @@ fn element(node: &NodeRef) -> Result<NodeDataRef<ElementData>> {
 }
 
 static SELECTOR_WHITESPACE: &[char] = &[' ', '\t', '\n', '\r', '\x0C'];
+fn document_doctype(node: &NodeRef) -> Result<Option<NodeRef>> {
+    if node.as_document().is_none() {
+        return Err(Error::runtime("Doctypes can only be accessed on document nodes."));
+    }
+    Ok(node.children().find(|x| x.as_doctype().is_some()))
+}
+
 fn split_classes(input: &str) -> impl Iterator<Item = &str> {
     struct SplitClasses<'a> {
         underlying: Split<'a, &'static [char]>,
@@ -113,35 +243,172 @@ fn check_class_name(name: &str) -> Result<()> {
     }
 }
 
+/// Checks that a comment's text can be serialized without ending the comment early, in both HTML and XML.
+fn check_comment_text(text: &str) -> Result<()> {
+    if text.contains("--") || text.starts_with('>') || text.starts_with("->") || text.ends_with('-')
+    {
+        Err(Error::runtime(
+            "Comment text cannot contain '--', start with '>' or '->', or end with '-'.",
+        ))
+    } else {
+        Ok(())
+    }
+}
+/// Checks that a processing instruction can be serialized without ending it early. HTML parses processing
+/// instructions as comments that end at the first `>`.
+fn check_processing_instruction(target: &str, data: &str) -> Result<()> {
+    if target.is_empty()
+        || target.contains(|x: char| x.is_ascii_whitespace() || x == '?' || x == '>')
+    {
+        Err(Error::runtime("Invalid processing instruction target."))
+    } else if data.contains('>') {
+        Err(Error::runtime("Processing instruction data cannot contain '>'."))
+    } else {
+        Ok(())
+    }
+}
+
+fn decode_with<'a>(lua: &Lua, text: &'a LuaString, encoding: &'static Encoding) -> Cow<'a, str> {
+    let (text, encoding, errors) = encoding.decode(text.as_bytes());
+    if errors {
+        encoding_warning(lua, encoding, false);
+    }
+    text
+}
+fn decode_text<'a>(
+    lua: &Lua,
+    text: &'a LuaString,
+    encoding: Option<LuaString>,
+    active_encoding_ref: &Rc<RefCell<&'static Encoding>>,
+) -> Result<Cow<'a, str>> {
+    let encoding = match encoding {
+        None => *active_encoding_ref.borrow(),
+        Some(encoding) => decode_encoding(&encoding)?,
+    };
+    Ok(decode_with(lua, text, encoding))
+}
+/// Decodes HTML source. Without an explicit encoding, the encoding is sniffed from a byte order mark or `<meta>` tag
+/// like browsers do, and the active encoding is only used if neither is found.
+fn decode_html<'a>(
+    lua: &Lua,
+    text: &'a LuaString,
+    encoding: Option<LuaString>,
+    active_encoding_ref: &Rc<RefCell<&'static Encoding>>,
+) -> Result<Cow<'a, str>> {
+    let encoding = match encoding {
+        None => sniff_encoding(text.as_bytes()).unwrap_or(*active_encoding_ref.borrow()),
+        Some(encoding) => decode_encoding(&encoding)?,
+    };
+    Ok(decode_with(lua, text, encoding))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ParseMode<'a> {
+    /// Parses the text as a document if it looks like one, and as a fragment in `<main>` otherwise.
+    Detect,
+    Document,
+    Fragment(&'a str),
+}
+
 fn parse<'lua>(
     lua: &'lua Lua,
     text: LuaString<'lua>,
     encoding: Option<LuaString<'lua>>,
-    force_document: bool,
-    force_fragment: bool,
-    fragment_root: &str,
+    mode: ParseMode,
+    source_name: Option<LuaString<'lua>>,
     active_encoding_ref: &Rc<RefCell<&'static Encoding>>,
+    source_locations_ref: &Rc<RefCell<SourceLocations>>,
 ) -> Result<LuaNodeRef> {
-    assert!(!(force_document && force_fragment));
+    let text = decode_html(lua, &text, encoding, active_encoding_ref)?;
+    let source = match &source_name {
+        Some(name) => Some(TextSource {
+            file: name.to_str()?,
+            source_locations: source_locations_ref,
+            markdown: None,
+        }),
+        None => None,
+    };
+    Ok(parse_text(&text, mode, source))
+}
 
-    let encoding = match encoding {
-        None => *active_encoding_ref.borrow(),
-        Some(encoding) => decode_encoding(&encoding)?,
+/// The file that text being parsed came from, so that the locations of its elements can be recorded.
+struct TextSource<'a> {
+    file: &'a str,
+    source_locations: &'a RefCell<SourceLocations>,
+    /// Maps locations in the text back to the file, if the text is HTML rendered from Markdown.
+    markdown: Option<&'a MarkdownSourceMap<'a>>,
+}
+
+/// Parses HTML text. If `source` is given, the location of each element is recorded under its file name.
+fn parse_text(text: &str, mode: ParseMode, source: Option<TextSource>) -> LuaNodeRef {
+    let fragment_root = match mode {
+        ParseMode::Detect if is_document(text) => None,
+        ParseMode::Detect => Some("main"),
+        ParseMode::Document => None,
+        ParseMode::Fragment(root) => Some(root),
     };
-    let (text, encoding, errors) = encoding.decode(text.as_bytes());
-    if errors {
-        encoding_warning(lua, encoding, false);
-    }
-    if (force_document || is_document(&text)) && !force_fragment {
-        Ok(LuaNodeRef(parse_html().one(&*text)))
+
+    let root = match source {
+        None => match fragment_root {
+            None => parse_html().one(text),
+            Some(root) => parse_fragment(qual_name(root), vec![]).one(text),
+        },
+        Some(source) => {
+            let (root, locations) = parse_with_locations(text, fragment_root);
+            let file: Rc<str> = Rc::from(source.file);
+            let mut source_locations = source.source_locations.borrow_mut();
+            for (node, line, column) in locations {
+                let (line, column) = match source.markdown {
+                    Some(map) => map.locate(text, line, column),
+                    None => (line, column),
+                };
+                source_locations.insert(&node, SourceLocation { file: file.clone(), line, column });
+            }
+            root
+        }
+    };
+
+    if fragment_root.is_none() {
+        LuaNodeRef(root)
     } else {
-        let fragment = parse_fragment(qual_name(fragment_root), vec![]).one(&*text);
         let new_root = NodeRef::new_document();
-        assert_eq!(fragment.children().count(), 1);
-        for child in fragment.children().next().unwrap().children() {
+        assert_eq!(root.children().count(), 1);
+        for child in root.children().next().unwrap().children() {
             new_root.append(child);
         }
-        Ok(LuaNodeRef(new_root))
+        LuaNodeRef(new_root)
+    }
+}
+
+/// The number of selector strings to keep compiled, so plugins that use the same selectors for every page don't
+/// parse them again each time.
+const SELECTOR_CACHE_SIZE: usize = 256;
+
+type SelectorCache = Rc<RefCell<LruCache<String, Rc<Selectors>>>>;
+
+fn compile_selector(cache: &SelectorCache, selector: &str) -> Result<Rc<Selectors>> {
+    let mut cache = cache.borrow_mut();
+    if let Some(selectors) = cache.get(selector) {
+        return Ok(selectors.clone());
+    }
+    let selectors = Selectors::compile(selector)
+        .map_err(|()| Error::runtime(format_args!("Could not parse selector: {selector}")))?;
+    let selectors = Rc::new(selectors);
+    cache.put(selector.to_string(), selectors.clone());
+    Ok(selectors)
+}
+
+/// Accepts either a selector string or a selector compiled with `HTML.compile_selector`.
+fn get_selector(cache: &SelectorCache, selector: Value) -> Result<Rc<Selectors>> {
+    match selector {
+        Value::String(str) => compile_selector(cache, str.to_str()?),
+        Value::UserData(data) if data.is::<LuaSelector>() => {
+            Ok(data.borrow::<LuaSelector>()?.0.clone())
+        }
+        value => Err(Error::runtime(format_args!(
+            "Expected a selector string or Selector, got {}",
+            value.type_name()
+        ))),
     }
 }
 
@@ -149,37 +416,177 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
     let table = lua.create_table()?;
 
     let active_encoding = Rc::new(RefCell::new(UTF_8));
+    let source_locations = Rc::new(RefCell::new(SourceLocations::default()));
+    let selector_cache: SelectorCache =
+        Rc::new(RefCell::new(LruCache::new(NonZeroUsize::new(SELECTOR_CACHE_SIZE).unwrap())));
 
     // Parsing and rendering
     {
         let active_encoding_ref = active_encoding.clone();
+        let source_locations_ref = source_locations.clone();
         table.raw_set(
             "parse",
-            lua.create_function(move |lua, (text, encoding): (LuaString, Option<LuaString>)| {
-                parse(lua, text, encoding, false, false, "main", &active_encoding_ref)
-            })?,
+            lua.create_function(
+                move |lua,
+                      (text, encoding, source_name): (
+                    LuaString,
+                    Option<LuaString>,
+                    Option<LuaString>,
+                )| {
+                    parse(
+                        lua,
+                        text,
+                        encoding,
+                        ParseMode::Detect,
+                        source_name,
+                        &active_encoding_ref,
+                        &source_locations_ref,
+                    )
+                },
+            )?,
         )?;
     }
     {
         let active_encoding_ref = active_encoding.clone();
+        let source_locations_ref = source_locations.clone();
         table.raw_set(
             "parse_document",
-            lua.create_function(move |lua, (text, encoding): (LuaString, Option<LuaString>)| {
-                parse(lua, text, encoding, true, false, "main", &active_encoding_ref)
-            })?,
+            lua.create_function(
+                move |lua,
+                      (text, encoding, source_name): (
+                    LuaString,
+                    Option<LuaString>,
+                    Option<LuaString>,
+                )| {
+                    parse(
+                        lua,
+                        text,
+                        encoding,
+                        ParseMode::Document,
+                        source_name,
+                        &active_encoding_ref,
+                        &source_locations_ref,
+                    )
+                },
+            )?,
         )?;
     }
     {
         let active_encoding_ref = active_encoding.clone();
+        table.raw_set(
+            "parse_errors",
+            lua.create_function(
+                move |lua,
+                      (text, encoding, document): (
+                    LuaString,
+                    Option<LuaString>,
+                    Option<bool>,
+                )| {
+                    let text = decode_html(lua, &text, encoding, &active_encoding_ref)?;
+                    let fragment_root = match document {
+                        Some(true) => None,
+                        _ if is_document(&text) => None,
+                        _ => Some("main"),
+                    };
+
+                    let errors = lua.create_table()?;
+                    for error in parse_errors(&text, fragment_root) {
+                        let entry = lua.create_table()?;
+                        entry.raw_set("line", error.line)?;
+                        entry.raw_set("message", error.message.as_ref())?;
+                        errors.raw_push(entry)?;
+                    }
+                    Ok(errors)
+                },
+            )?,
+        )?;
+    }
+    {
+        let source_locations_ref = source_locations.clone();
+        table.raw_set(
+            "source_location",
+            lua.create_function(
+                move |lua, node: UserDataRef<LuaNodeRef>| match source_locations_ref
+                    .borrow()
+                    .get(&node.0)
+                {
+                    Some(location) => {
+                        let table = lua.create_table()?;
+                        table.raw_set("file", &*location.file)?;
+                        table.raw_set("line", location.line)?;
+                        table.raw_set("column", location.column)?;
+                        Ok(Some(table))
+                    }
+                    None => Ok(None),
+                },
+            )?,
+        )?;
+    }
+    {
+        let active_encoding_ref = active_encoding.clone();
+        let source_locations_ref = source_locations.clone();
         table.raw_set(
             "parse_fragment",
             lua.create_function(
-                move |lua, (text, tag, encoding): (LuaString, LuaString, Option<LuaString>)| {
-                    parse(lua, text, encoding, false, true, tag.to_str()?, &active_encoding_ref)
+                move |lua,
+                      (text, tag, encoding, source_name): (
+                    LuaString,
+                    LuaString,
+                    Option<LuaString>,
+                    Option<LuaString>,
+                )| {
+                    parse(
+                        lua,
+                        text,
+                        encoding,
+                        ParseMode::Fragment(tag.to_str()?),
+                        source_name,
+                        &active_encoding_ref,
+                        &source_locations_ref,
+                    )
+                },
+            )?,
+        )?;
+    }
+    {
+        let active_encoding_ref = active_encoding.clone();
+        let source_locations_ref = source_locations.clone();
+        table.raw_set(
+            "parse_markdown",
+            lua.create_function(
+                move |lua,
+                      (text, encoding, source_name): (
+                    LuaString,
+                    Option<LuaString>,
+                    Option<LuaString>,
+                )| {
+                    let text = decode_text(lua, &text, encoding, &active_encoding_ref)?;
+                    let Some(source_name) = source_name else {
+                        let html = markdown_to_html(&text);
+                        return Ok(parse_text(&html, ParseMode::Fragment("main"), None));
+                    };
+
+                    let (html, map) = markdown_to_html_with_source_map(&text);
+                    let source = TextSource {
+                        file: source_name.to_str()?,
+                        source_locations: &source_locations_ref,
+                        markdown: Some(&map),
+                    };
+                    Ok(parse_text(&html, ParseMode::Fragment("main"), Some(source)))
                 },
             )?,
         )?;
     }
+    {
+        let active_encoding_ref = active_encoding.clone();
+        table.raw_set(
+            "parse_xml",
+            lua.create_function(move |lua, (text, encoding): (LuaString, Option<LuaString>)| {
+                let text = decode_text(lua, &text, encoding, &active_encoding_ref)?;
+                Ok(LuaNodeRef(parse_xml(&text)))
+            })?,
+        )?;
+    }
     {
         let active_encoding_ref = active_encoding.clone();
         table.raw_set(
@@ -195,8 +602,22 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
         table.raw_set(
             "to_string",
             lua.create_function(
-                move |lua, (node_ref, encoding): (UserDataRef<LuaNodeRef>, Option<LuaString>)| {
-                    html_to_string(lua, &node_ref.0, &encoding, &active_encoding_ref, false)
+                move |lua,
+                      (node_ref, encoding, options): (
+                    UserDataRef<LuaNodeRef>,
+                    Option<LuaString>,
+                    Option<Table>,
+                )| {
+                    let pretty = match &options {
+                        Some(options) => options.get::<_, Option<bool>>("pretty_print")?,
+                        None => None,
+                    };
+                    let mode = if pretty.unwrap_or(false) {
+                        OutputMode::PrettyPrint(pretty_print_options(options)?)
+                    } else {
+                        OutputMode::Plain
+                    };
+                    html_to_string(lua, &node_ref.0, &encoding, &active_encoding_ref, mode)
                 },
             )?,
         )?;
@@ -205,13 +626,42 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
         let active_encoding_ref = active_encoding.clone();
         table.raw_set(
             "pretty_print",
+            lua.create_function(
+                move |lua,
+                      (node_ref, encoding, options): (
+                    UserDataRef<LuaNodeRef>,
+                    Option<LuaString>,
+                    Option<Table>,
+                )| {
+                    let mode = OutputMode::PrettyPrint(pretty_print_options(options)?);
+                    html_to_string(lua, &node_ref.0, &encoding, &active_encoding_ref, mode)
+                },
+            )?,
+        )?;
+    }
+    {
+        let active_encoding_ref = active_encoding.clone();
+        table.raw_set(
+            "minify",
             lua.create_function(
                 move |lua, (node_ref, encoding): (UserDataRef<LuaNodeRef>, Option<LuaString>)| {
-                    html_to_string(lua, &node_ref.0, &encoding, &active_encoding_ref, true)
+                    html_to_string(
+                        lua,
+                        &node_ref.0,
+                        &encoding,
+                        &active_encoding_ref,
+                        OutputMode::Minify,
+                    )
                 },
             )?,
         )?;
     }
+    table.raw_set(
+        "to_xml_string",
+        lua.create_function(|lua, node_ref: UserDataRef<LuaNodeRef>| {
+            lua.create_string(to_xml_string(&node_ref.0))
+        })?,
+    )?;
     {
         let active_encoding_ref = active_encoding.clone();
         table.raw_set(
@@ -255,6 +705,24 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
             Ok(LuaNodeRef(elem))
         })?,
     )?;
+    table.raw_set(
+        "create_element_ns",
+        lua.create_function(
+            |_, (namespace, name, text): (LuaString, LuaString, Option<LuaString>)| {
+                let (prefix, local) = split_qual_name(name.to_str()?);
+                let name = QualName::new(
+                    prefix,
+                    Namespace::from(namespace.to_str()?),
+                    LocalName::from(local),
+                );
+                let elem = NodeRef::new_element(name, vec![]);
+                if let Some(text) = text {
+                    elem.append(NodeRef::new_text(text.to_str()?));
+                }
+                Ok(LuaNodeRef(elem))
+            },
+        )?,
+    )?;
     table.raw_set(
         "create_text",
         lua.create_function(|_, text: LuaString| {
@@ -262,49 +730,80 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
         })?,
     )?;
     table.raw_set(
-        "clone",
-        lua.create_function(|_, elem: UserDataRef<LuaNodeRef>| {
-            Ok(LuaNodeRef(clone_node(&elem.0)))
+        "create_comment",
+        lua.create_function(|_, text: LuaString| {
+            let text = text.to_str()?;
+            check_comment_text(text)?;
+            Ok(LuaNodeRef(NodeRef::new_comment(text)))
         })?,
     )?;
+    table.raw_set(
+        "create_processing_instruction",
+        lua.create_function(|_, (target, data): (LuaString, LuaString)| {
+            let (target, data) = (target.to_str()?, data.to_str()?);
+            check_processing_instruction(target, data)?;
+            Ok(LuaNodeRef(NodeRef::new_processing_instruction(target, data)))
+        })?,
+    )?;
+    {
+        let source_locations_ref = source_locations.clone();
+        table.raw_set(
+            "clone",
+            lua.create_function(move |_, elem: UserDataRef<LuaNodeRef>| {
+                let clone = clone_node(&elem.0);
+                source_locations_ref
+                    .borrow_mut()
+                    .copy_to_clone(&elem.0, &clone);
+                Ok(LuaNodeRef(clone))
+            })?,
+        )?;
+    }
 
     // Selection and selector match checking
     // - Implemented in Lua: HTML.select_any_of - note: crabsoup uses selector lists
     // - Implemented in Lua: HTML.select_all_of - note: crabsoup uses selector lists
     // - Implemented in Lua: HTML.matches_selector - note: crabsoup doesn't need the document ref
     // - Implemented in Lua: HTML.matches_any_of_selectors - note: crabsoup uses selector lists
-    table.raw_set(
-        "select",
-        lua.create_function(|lua, (html, selector): (UserDataRef<LuaNodeRef>, LuaString)| {
-            let selector = selector.to_str()?;
-            let table = lua.create_table()?;
-            for elem in html.0.select(selector).map_err(|()| {
-                Error::runtime(format_args!("Could not parse selector: {selector}"))
-            })? {
-                table.raw_push(LuaNodeRef(elem.as_node().clone()))?;
-            }
-            Ok(table)
-        })?,
-    )?;
-    table.raw_set(
-        "select_one",
-        lua.create_function(|_, (html, selector): (UserDataRef<LuaNodeRef>, LuaString)| {
-            let selector = selector.to_str()?;
-            Ok(html
-                .0
-                .select(selector)
-                .map_err(|()| Error::runtime(format_args!("Could not parse selector: {selector}")))?
-                .next()
-                .map(|x| LuaNodeRef(x.as_node().clone())))
-        })?,
-    )?;
+    {
+        let selector_cache = selector_cache.clone();
+        table.raw_set(
+            "compile_selector",
+            lua.create_function(move |_, selector: LuaString| {
+                Ok(LuaSelector(compile_selector(&selector_cache, selector.to_str()?)?))
+            })?,
+        )?;
+    }
+    {
+        let selector_cache = selector_cache.clone();
+        table.raw_set(
+            "select",
+            lua.create_function(move |lua, (html, selector): (UserDataRef<LuaNodeRef>, Value)| {
+                let selectors = get_selector(&selector_cache, selector)?;
+                let table = lua.create_table()?;
+                for elem in selectors.filter(html.0.inclusive_descendants().elements()) {
+                    table.raw_push(LuaNodeRef(elem.as_node().clone()))?;
+                }
+                Ok(table)
+            })?,
+        )?;
+    }
+    {
+        let selector_cache = selector_cache.clone();
+        table.raw_set(
+            "select_one",
+            lua.create_function(move |_, (html, selector): (UserDataRef<LuaNodeRef>, Value)| {
+                let selectors = get_selector(&selector_cache, selector)?;
+                Ok(selectors
+                    .filter(html.0.inclusive_descendants().elements())
+                    .next()
+                    .map(|x| LuaNodeRef(x.as_node().clone())))
+            })?,
+        )?;
+    }
     table.raw_set(
         "matches",
-        lua.create_function(|_, (node, selector): (UserDataRef<LuaNodeRef>, LuaString)| {
-            let selector = selector.to_str()?;
-            let selectors = Selectors::compile(selector).map_err(|()| {
-                Error::runtime(format_args!("Could not parse selector: {selector}"))
-            })?;
+        lua.create_function(move |_, (node, selector): (UserDataRef<LuaNodeRef>, Value)| {
+            let selectors = get_selector(&selector_cache, selector)?;
             if let Some(elem) = node.0.clone().into_element_ref() {
                 Ok(selectors.matches(&elem))
             } else {
@@ -376,6 +875,15 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
             Ok(node.0.children().next().is_none())
         })?,
     )?;
+    table.raw_set(
+        "template_contents",
+        lua.create_function(|_, node: UserDataRef<LuaNodeRef>| {
+            match &element(&node.0)?.template_contents {
+                Some(contents) => Ok(LuaNodeRef(contents.clone())),
+                None => Err(Error::runtime("Node is not a <template> element.")),
+            }
+        })?,
+    )?;
 
     // Element property access and manipulation
     // - Implemented in Lua: HTML.append_attribute
@@ -414,6 +922,38 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
             },
         )?,
     )?;
+    table.raw_set(
+        "get_attribute_ns",
+        lua.create_function(
+            |lua, (node, namespace, name): (UserDataRef<LuaNodeRef>, LuaString, LuaString)| {
+                let name = ExpandedName::new(namespace.to_str()?, name.to_str()?);
+                if let Some(attr) = element(&node.0)?.attributes.borrow().map.get(&name) {
+                    Ok(Some(lua.create_string(&attr.value)?))
+                } else {
+                    Ok(None)
+                }
+            },
+        )?,
+    )?;
+    table.raw_set(
+        "set_attribute_ns",
+        lua.create_function(
+            |_,
+             (node, namespace, name, value): (
+                UserDataRef<LuaNodeRef>,
+                LuaString,
+                LuaString,
+                LuaString,
+            )| {
+                let (prefix, local) = split_qual_name(name.to_str()?);
+                element(&node.0)?.attributes.borrow_mut().map.insert(
+                    ExpandedName::new(namespace.to_str()?, local),
+                    Attribute { prefix, value: value.to_string_lossy().to_string() },
+                );
+                Ok(())
+            },
+        )?,
+    )?;
     table.raw_set(
         "delete_attribute",
         lua.create_function(|_, (node, name): (UserDataRef<LuaNodeRef>, LuaString)| {
@@ -515,6 +1055,72 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
             Ok(())
         })?,
     )?;
+    table.raw_set(
+        "get_style",
+        lua.create_function(|lua, node: UserDataRef<LuaNodeRef>| {
+            let elem = element(&node.0)?;
+            let attrs = elem.attributes.borrow();
+            let table = lua.create_table()?;
+            if let Some(style) = attrs.get("style") {
+                for declaration in effective_style(&parse_style(style)) {
+                    if declaration.important {
+                        let value = format!("{} !important", declaration.value);
+                        table.raw_set(declaration.name.as_str(), value)?;
+                    } else {
+                        table.raw_set(declaration.name.as_str(), declaration.value.as_str())?;
+                    }
+                }
+            }
+            Ok(table)
+        })?,
+    )?;
+    table.raw_set(
+        "set_style",
+        lua.create_function(
+            |_, (node, name, value): (UserDataRef<LuaNodeRef>, LuaString, LuaString)| {
+                let elem = element(&node.0)?;
+                let Some(new) = parse_single_declaration(name.to_str()?, value.to_str()?) else {
+                    return Err(Error::runtime("Invalid CSS declaration."));
+                };
+
+                let mut attrs = elem.attributes.borrow_mut();
+                let mut declarations = parse_style(attrs.get("style").unwrap_or_default());
+                match declarations.iter().position(|x| x.name == new.name) {
+                    Some(index) => {
+                        // Replace the first declaration of the property, and drop any later ones
+                        declarations[index] = new.clone();
+                        let mut seen = false;
+                        declarations.retain(|x| {
+                            let is_duplicate = seen && x.name == new.name;
+                            seen |= x.name == new.name;
+                            !is_duplicate
+                        });
+                    }
+                    None => declarations.push(new),
+                }
+                attrs.insert("style", serialize_style(&declarations));
+                Ok(())
+            },
+        )?,
+    )?;
+    table.raw_set(
+        "remove_style",
+        lua.create_function(|_, (node, name): (UserDataRef<LuaNodeRef>, LuaString)| {
+            let elem = element(&node.0)?;
+            let mut attrs = elem.attributes.borrow_mut();
+            if let Some(style) = attrs.get("style") {
+                let name = normalize_property(name.to_str()?);
+                let mut declarations = parse_style(style);
+                declarations.retain(|x| x.name != name);
+                if declarations.is_empty() {
+                    attrs.remove("style");
+                } else {
+                    attrs.insert("style", serialize_style(&declarations));
+                }
+            }
+            Ok(())
+        })?,
+    )?;
     table.raw_set(
         "inner_text",
         lua.create_function(|lua, node: UserDataRef<LuaNodeRef>| {
@@ -528,6 +1134,93 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
         })?,
     )?;
 
+    // Text, comment, processing instruction and doctype access
+    table.raw_set(
+        "get_text",
+        lua.create_function(|lua, node: UserDataRef<LuaNodeRef>| match node.0.data() {
+            NodeData::Text(text) | NodeData::Comment(text) => lua.create_string(&*text.borrow()),
+            NodeData::ProcessingInstruction(pi) => lua.create_string(&pi.borrow().1),
+            _ => Err(Error::runtime("Node is not a text, comment or processing instruction node.")),
+        })?,
+    )?;
+    table.raw_set(
+        "set_text",
+        lua.create_function(|_, (node, value): (UserDataRef<LuaNodeRef>, LuaString)| {
+            let value = value.to_str()?.to_string();
+            match node.0.data() {
+                NodeData::Text(text) => *text.borrow_mut() = value,
+                NodeData::Comment(text) => {
+                    check_comment_text(&value)?;
+                    *text.borrow_mut() = value;
+                }
+                NodeData::ProcessingInstruction(pi) => {
+                    let mut pi = pi.borrow_mut();
+                    check_processing_instruction(&pi.0, &value)?;
+                    pi.1 = value;
+                }
+                _ => {
+                    return Err(Error::runtime(
+                        "Node is not a text, comment or processing instruction node.",
+                    ))
+                }
+            }
+            Ok(())
+        })?,
+    )?;
+    table.raw_set(
+        "get_processing_instruction_target",
+        lua.create_function(|lua, node: UserDataRef<LuaNodeRef>| match node.0.data() {
+            NodeData::ProcessingInstruction(pi) => lua.create_string(&pi.borrow().0),
+            _ => Err(Error::runtime("Node is not a processing instruction node.")),
+        })?,
+    )?;
+    table.raw_set(
+        "get_doctype",
+        lua.create_function(|lua, node: UserDataRef<LuaNodeRef>| {
+            let Some(doctype) = document_doctype(&node.0)? else {
+                return Ok(None);
+            };
+            let doctype = doctype.as_doctype().unwrap();
+            let table = lua.create_table()?;
+            table.raw_set("name", doctype.name.as_str())?;
+            table.raw_set("public_id", doctype.public_id.as_str())?;
+            table.raw_set("system_id", doctype.system_id.as_str())?;
+            Ok(Some(table))
+        })?,
+    )?;
+    table.raw_set(
+        "set_doctype",
+        lua.create_function(
+            |_,
+             (node, name, public_id, system_id): (
+                UserDataRef<LuaNodeRef>,
+                LuaString,
+                Option<LuaString>,
+                Option<LuaString>,
+            )| {
+                let new_doctype = NodeRef::new_doctype(
+                    name.to_str()?,
+                    match &public_id {
+                        Some(x) => x.to_str()?,
+                        None => "",
+                    },
+                    match &system_id {
+                        Some(x) => x.to_str()?,
+                        None => "",
+                    },
+                );
+                match document_doctype(&node.0)? {
+                    Some(doctype) => {
+                        doctype.insert_before(new_doctype);
+                        doctype.detach();
+                    }
+                    None => node.0.prepend(new_doctype),
+                }
+                Ok(())
+            },
+        )?,
+    )?;
+
     // Element tree manipulation
     // - Implemented in lua: HTML.append_root - legacy-only
     // - Implemented in lua: HTML.prepend_root - legacy-only
@@ -639,6 +1332,40 @@ pub fn create_html_table(lua: &Lua) -> Result<Table> {
     // High-level convenience functions
     // - Implemented in lua: HTML.get_heading_level
     // - Implemented in lua: HTML.get_headings_tree
+    table.raw_set(
+        "sanitize",
+        lua.create_function(|_, (node, policy): (UserDataRef<LuaNodeRef>, Option<Table>)| {
+            sanitize(&node.0, &sanitize_policy(policy)?);
+            Ok(())
+        })?,
+    )?;
+    table.raw_set(
+        "diff",
+        lua.create_function(
+            |lua,
+             (old, new, options): (
+                UserDataRef<LuaNodeRef>,
+                UserDataRef<LuaNodeRef>,
+                Option<Table>,
+            )| {
+                let mut diff_options = DiffOptions::default();
+                if let Some(options) = options {
+                    if let Some(value) = options.get::<_, Option<bool>>("ignore_whitespace")? {
+                        diff_options.ignore_whitespace = value;
+                    }
+                    if let Some(value) = options.get::<_, Option<bool>>("ignore_attribute_order")? {
+                        diff_options.ignore_attribute_order = value;
+                    }
+                }
+
+                let table = lua.create_table()?;
+                for change in diff(&old.0, &new.0, &diff_options) {
+                    table.raw_push(change_to_table(lua, change)?)?;
+                }
+                Ok(table)
+            },
+        )?,
+    )?;
 
     // Node tests
     table.raw_set(
@@ -690,3 +1417,16 @@ impl UserData for LuaNodeRef {
         fields.add_meta_field("__type", "NodeRef");
     }
 }
+
+/// A selector list compiled with `HTML.compile_selector`.
+#[derive(Clone)]
+struct LuaSelector(Rc<Selectors>);
+impl UserData for LuaSelector {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field("__type", "Selector");
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method("__tostring", |_, this, ()| Ok(this.0.to_string()));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::{libs::process::ASYNC_EXECUTOR, paths::lstr_to_system_path};
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageReader};
+use kuchikiki::{parse_html, traits::TendrilSink};
+use mlua::{
+    prelude::LuaString, Error, Lua, Result, Table, UserData, UserDataFields, UserDataRef,
+    UserDataRefMut,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::task::JoinHandle;
+
+/// Parses an SVG length such as `120` or `120px`. Relative units can't be resolved, and are ignored.
+fn parse_svg_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value.strip_suffix("px").unwrap_or(value);
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|x| x.is_finite() && *x > 0.0)
+}
+
+fn svg_dimensions(path: &Path) -> Result<Option<(u32, u32)>> {
+    let data = std::fs::read_to_string(path)?;
+    let document = parse_html().one(data);
+    let Ok(svg) = document.select_first("svg") else {
+        return Ok(None);
+    };
+    let attrs = svg.attributes.borrow();
+
+    let width = attrs.get("width").and_then(parse_svg_length);
+    let height = attrs.get("height").and_then(parse_svg_length);
+    let view_box: Option<Vec<f64>> = attrs.get("viewBox").and_then(|x| {
+        x.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<f64>().ok())
+            .collect()
+    });
+    let view_box = match view_box.as_deref() {
+        Some(&[_, _, w, h]) if w > 0.0 && h > 0.0 => Some((w, h)),
+        _ => None,
+    };
+
+    let dimensions = match (width, height, view_box) {
+        (Some(w), Some(h), _) => Some((w, h)),
+        (Some(w), None, Some((vw, vh))) => Some((w, w * vh / vw)),
+        (None, Some(h), Some((vw, vh))) => Some((h * vw / vh, h)),
+        (None, None, Some(view_box)) => Some(view_box),
+        _ => None,
+    };
+    Ok(dimensions.map(|(w, h)| (w.round() as u32, h.round() as u32)))
+}
+
+fn image_dimensions(path: &Path) -> Result<Option<(u32, u32)>> {
+    let is_svg = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        return svg_dimensions(path);
+    }
+
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    if reader.format().is_none() {
+        return Ok(None);
+    }
+    match reader.into_dimensions() {
+        Ok(dimensions) => Ok(Some(dimensions)),
+        Err(e) => Err(Error::runtime(format_args!("Could not read {}: {e}", path.display()))),
+    }
+}
+
+fn output_format(target: &Path) -> Result<ImageFormat> {
+    match ImageFormat::from_path(target) {
+        Ok(format) if format.writing_enabled() => Ok(format),
+        _ => Err(Error::runtime(format_args!(
+            "Unsupported output format for resized image: {}",
+            target.display()
+        ))),
+    }
+}
+
+fn resize_image(source: &Path, image: &DynamicImage, target: &Path, width: u32) -> Result<()> {
+    let format = output_format(target)?;
+    let height = ((image.height() as f64 * width as f64) / image.width() as f64)
+        .round()
+        .max(1.0);
+    let resized = image.resize_exact(width, height as u32, FilterType::Lanczos3);
+    let resized = match format {
+        // JPEG can't store an alpha channel
+        ImageFormat::Jpeg => resized.into_rgb8().into(),
+        _ => resized,
+    };
+    resized
+        .save_with_format(target, format)
+        .map_err(|e| Error::runtime(format_args!("Could not resize {}: {e}", source.display())))
+}
+
+/// Decodes an image once, and resizes it to every target in parallel on background threads.
+async fn resize_all(source: PathBuf, targets: Vec<(PathBuf, u32)>) -> Result<()> {
+    if targets.is_empty() {
+        return Ok(());
+    }
+    let decode_source = source.clone();
+    let image = ASYNC_EXECUTOR
+        .spawn_blocking(move || -> Result<DynamicImage> {
+            ImageReader::open(&decode_source)?
+                .with_guessed_format()?
+                .decode()
+                .map_err(|e| {
+                    Error::runtime(format_args!(
+                        "Could not resize {}: {e}",
+                        decode_source.display()
+                    ))
+                })
+        })
+        .await
+        .map_err(Error::external)??;
+    let image = Arc::new(image);
+
+    let mut handles = Vec::new();
+    for (target, width) in targets {
+        let source = source.clone();
+        let image = image.clone();
+        handles.push(
+            ASYNC_EXECUTOR.spawn_blocking(move || resize_image(&source, &image, &target, width)),
+        );
+    }
+    for handle in handles {
+        handle.await.map_err(Error::external)??;
+    }
+    Ok(())
+}
+
+/// An image being resized in the background, which can be waited on like a process.
+struct LuaImageJob {
+    handle: Option<JoinHandle<Result<()>>>,
+}
+impl LuaImageJob {
+    fn spawn(source: LuaString, targets: Table) -> Result<LuaImageJob> {
+        let source = lstr_to_system_path(source)?;
+        let mut targets_vec = Vec::new();
+        for pair in targets.pairs::<LuaString, u32>() {
+            let (target, width) = pair?;
+            if width == 0 {
+                return Err(Error::runtime("Width must be positive."));
+            }
+            let target = lstr_to_system_path(target)?;
+            output_format(&target)?;
+            targets_vec.push((target, width));
+        }
+        Ok(LuaImageJob { handle: Some(ASYNC_EXECUTOR.spawn(resize_all(source, targets_vec))) })
+    }
+
+    fn check_is_completed(&self) -> Result<bool> {
+        match &self.handle {
+            Some(handle) => Ok(handle.is_finished()),
+            None => Err(Error::runtime("Image job has already been awaited.")),
+        }
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        match self.handle.take() {
+            Some(handle) => ASYNC_EXECUTOR.block_on(handle).map_err(Error::external)?,
+            None => Err(Error::runtime("Image job has already been awaited.")),
+        }
+    }
+}
+impl UserData for LuaImageJob {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field("__type", "ImageJob");
+    }
+}
+
+pub fn create_image_table(lua: &Lua) -> Result<Table> {
+    let table = lua.create_table()?;
+
+    table.raw_set(
+        "get_dimensions",
+        lua.create_function(|_, path: LuaString| {
+            match image_dimensions(&lstr_to_system_path(path)?)? {
+                Some((width, height)) => Ok((Some(width), Some(height))),
+                None => Ok((None, None)),
+            }
+        })?,
+    )?;
+    table.raw_set(
+        "resize",
+        lua.create_function(|_, (source, targets): (LuaString, Table)| {
+            LuaImageJob::spawn(source, targets)?.wait()
+        })?,
+    )?;
+    table.raw_set(
+        "spawn_resize",
+        lua.create_function(|_, (source, targets): (LuaString, Table)| {
+            LuaImageJob::spawn(source, targets)
+        })?,
+    )?;
+    table.raw_set(
+        "is_completed",
+        lua.create_function(|_, job: UserDataRef<LuaImageJob>| job.check_is_completed())?,
+    )?;
+    table.raw_set(
+        "wait_on",
+        lua.create_function(|_, mut job: UserDataRefMut<LuaImageJob>| job.wait())?,
+    )?;
+
+    Ok(table)
+}
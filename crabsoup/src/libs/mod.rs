@@ -1,9 +1,12 @@
 pub mod analyze;
 pub mod base;
 pub mod codec;
+pub mod compress;
 pub mod date;
 pub mod digest;
+pub mod highlight;
 pub mod html;
+pub mod image;
 pub mod log;
 pub mod process;
 pub mod regex;
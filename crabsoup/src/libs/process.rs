@@ -14,7 +14,7 @@ use tokio::{
     task::JoinHandle,
 };
 
-static ASYNC_EXECUTOR: LazyLock<Runtime> = LazyLock::new(|| {
+pub(crate) static ASYNC_EXECUTOR: LazyLock<Runtime> = LazyLock::new(|| {
     Builder::new_multi_thread()
         .thread_name("Crabsoup - Process Manager Thread")
         .worker_threads(1)
@@ -1,63 +1,170 @@
-use mlua::{prelude::LuaString, Error, Lua, Result, Table};
+use lru::LruCache;
+use mlua::{
+    prelude::LuaString, Error, Lua, Result, Table, UserData, UserDataFields, UserDataMethods, Value,
+};
+use regex::{Captures, Regex, RegexBuilder};
+use std::{cell::RefCell, num::NonZeroUsize, rc::Rc};
+
+/// The number of patterns to keep compiled for the string-based functions, so plugins that use the same pattern
+/// for every page don't compile it again each time.
+const REGEX_CACHE_SIZE: usize = 256;
+
+type RegexCache = Rc<RefCell<LruCache<String, Regex>>>;
+
+fn cached_regex(cache: &RegexCache, pattern: &LuaString) -> Result<Regex> {
+    let pattern = pattern.to_str()?;
+    let mut cache = cache.borrow_mut();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+    let regex = Regex::new(pattern).map_err(Error::runtime)?;
+    cache.put(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn compile_regex(pattern: &str, flags: Option<&str>) -> Result<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.unwrap_or_default().chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            'U' => builder.swap_greed(true),
+            _ => return Err(Error::runtime(format_args!("Unknown regex flag: {flag}"))),
+        };
+    }
+    builder.build().map_err(Error::runtime)
+}
+
+/// Creates a table of the groups in a match, with the whole match at index 0 and named groups also stored by name.
+/// Groups that did not participate in the match are left out.
+fn captures_table<'lua>(lua: &'lua Lua, regex: &Regex, captures: &Captures) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    for (i, name) in regex.capture_names().enumerate() {
+        if let Some(m) = captures.get(i) {
+            table.raw_set(i, m.as_str())?;
+            if let Some(name) = name {
+                table.raw_set(name, m.as_str())?;
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// Replaces the first `limit` matches (or all matches, if `limit` is 0) with either a template string, or the
+/// result of calling a function with the captures of each match.
+fn replace_matches<'lua>(
+    lua: &'lua Lua,
+    regex: &Regex,
+    text: &str,
+    replacement: Value<'lua>,
+    limit: usize,
+) -> Result<LuaString<'lua>> {
+    match replacement {
+        Value::String(template) => {
+            lua.create_string(regex.replacen(text, limit, template.to_str()?).as_bytes())
+        }
+        Value::Function(func) => {
+            let limit = if limit == 0 { usize::MAX } else { limit };
+            let mut accum = String::new();
+            let mut last_end = 0;
+            for captures in regex.captures_iter(text).take(limit) {
+                let whole = captures.get(0).unwrap();
+                accum.push_str(&text[last_end..whole.start()]);
+                match func.call::<_, Value>(captures_table(lua, regex, &captures)?)? {
+                    Value::Nil | Value::Boolean(false) => accum.push_str(whole.as_str()),
+                    value => match lua.coerce_string(value)? {
+                        Some(str) => accum.push_str(str.to_str()?),
+                        None => {
+                            return Err(Error::runtime(
+                                "Replacement function must return a string or nil.",
+                            ))
+                        }
+                    },
+                }
+                last_end = whole.end();
+            }
+            accum.push_str(&text[last_end..]);
+            lua.create_string(&accum)
+        }
+        value => Err(Error::runtime(format_args!(
+            "Replacement must be a string or function, got {}",
+            value.type_name()
+        ))),
+    }
+}
 
 pub fn create_regex_table(lua: &Lua) -> Result<Table> {
     let table = lua.create_table()?;
 
+    let regex_cache: RegexCache =
+        Rc::new(RefCell::new(LruCache::new(NonZeroUsize::new(REGEX_CACHE_SIZE).unwrap())));
+
     table.raw_set(
-        "match",
-        lua.create_function(|_, (string, regex): (LuaString, LuaString)| {
-            Ok(regex::Regex::new(regex.to_str()?)
-                .map_err(Error::runtime)?
-                .is_match(string.to_str()?))
-        })?,
-    )?;
-    table.raw_set(
-        "find_all",
-        lua.create_function(|lua, (string, regex): (LuaString, LuaString)| {
-            let table = lua.create_table()?;
-            for m in regex::Regex::new(regex.to_str()?)
-                .map_err(Error::runtime)?
-                .find_iter(string.to_str()?)
-            {
-                table.raw_push(m.as_str())?;
-            }
-            Ok(table)
+        "compile",
+        lua.create_function(|_, (pattern, flags): (LuaString, Option<LuaString>)| {
+            let flags = flags.as_ref().map(|x| x.to_str()).transpose()?;
+            Ok(LuaRegex(compile_regex(pattern.to_str()?, flags)?))
         })?,
     )?;
-    table.raw_set(
-        "replace",
-        lua.create_function(
-            |lua, (string, regex, replacement): (LuaString, LuaString, LuaString)| {
-                Ok(lua.create_string(
-                    regex::Regex::new(regex.to_str()?)
-                        .map_err(Error::runtime)?
-                        .replace(string.to_str()?, replacement.to_str()?)
-                        .as_bytes(),
-                )?)
-            },
-        )?,
-    )?;
-    table.raw_set(
-        "replace_all",
-        lua.create_function(
-            |lua, (string, regex, replacement): (LuaString, LuaString, LuaString)| {
-                Ok(lua.create_string(
-                    regex::Regex::new(regex.to_str()?)
-                        .map_err(Error::runtime)?
-                        .replace_all(string.to_str()?, replacement.to_str()?)
-                        .as_bytes(),
-                )?)
-            },
-        )?,
-    )?;
+    {
+        let regex_cache = regex_cache.clone();
+        table.raw_set(
+            "match",
+            lua.create_function(move |_, (string, regex): (LuaString, LuaString)| {
+                Ok(cached_regex(&regex_cache, &regex)?.is_match(string.to_str()?))
+            })?,
+        )?;
+    }
+    {
+        let regex_cache = regex_cache.clone();
+        table.raw_set(
+            "find_all",
+            lua.create_function(move |lua, (string, regex): (LuaString, LuaString)| {
+                let table = lua.create_table()?;
+                for m in cached_regex(&regex_cache, &regex)?.find_iter(string.to_str()?) {
+                    table.raw_push(m.as_str())?;
+                }
+                Ok(table)
+            })?,
+        )?;
+    }
+    {
+        let regex_cache = regex_cache.clone();
+        table.raw_set(
+            "replace",
+            lua.create_function(
+                move |lua, (string, regex, replacement): (LuaString, LuaString, LuaString)| {
+                    Ok(lua.create_string(
+                        cached_regex(&regex_cache, &regex)?
+                            .replace(string.to_str()?, replacement.to_str()?)
+                            .as_bytes(),
+                    )?)
+                },
+            )?,
+        )?;
+    }
+    {
+        let regex_cache = regex_cache.clone();
+        table.raw_set(
+            "replace_all",
+            lua.create_function(
+                move |lua, (string, regex, replacement): (LuaString, LuaString, LuaString)| {
+                    Ok(lua.create_string(
+                        cached_regex(&regex_cache, &regex)?
+                            .replace_all(string.to_str()?, replacement.to_str()?)
+                            .as_bytes(),
+                    )?)
+                },
+            )?,
+        )?;
+    }
     table.raw_set(
         "split",
-        lua.create_function(|lua, (string, regex): (LuaString, LuaString)| {
+        lua.create_function(move |lua, (string, regex): (LuaString, LuaString)| {
             let table = lua.create_table()?;
-            for m in regex::Regex::new(regex.to_str()?)
-                .map_err(Error::runtime)?
-                .split(string.to_str()?)
-            {
+            for m in cached_regex(&regex_cache, &regex)?.split(string.to_str()?) {
                 table.raw_push(m)?;
             }
             Ok(table)
@@ -70,3 +177,62 @@ pub fn create_regex_table(lua: &Lua) -> Result<Table> {
 
     Ok(table)
 }
+
+/// A regex compiled with `Regex.compile`.
+struct LuaRegex(Regex);
+impl UserData for LuaRegex {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_meta_field("__type", "Regex");
+    }
+
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method("__tostring", |_, this, ()| Ok(this.0.as_str().to_string()));
+
+        methods.add_method("match", |_, this, string: LuaString| {
+            Ok(this.0.is_match(string.to_str()?))
+        });
+        methods.add_method("find_all", |lua, this, string: LuaString| {
+            let table = lua.create_table()?;
+            for m in this.0.find_iter(string.to_str()?) {
+                table.raw_push(m.as_str())?;
+            }
+            Ok(table)
+        });
+        methods.add_method("captures", |lua, this, string: LuaString| {
+            match this.0.captures(string.to_str()?) {
+                Some(captures) => Ok(Some(captures_table(lua, &this.0, &captures)?)),
+                None => Ok(None),
+            }
+        });
+        methods.add_method("captures_all", |lua, this, string: LuaString| {
+            let table = lua.create_table()?;
+            for captures in this.0.captures_iter(string.to_str()?) {
+                table.raw_push(captures_table(lua, &this.0, &captures)?)?;
+            }
+            Ok(table)
+        });
+        methods.add_method("replace", |lua, this, (string, replacement): (LuaString, Value)| {
+            replace_matches(lua, &this.0, string.to_str()?, replacement, 1)
+        });
+        methods.add_method(
+            "replace_all",
+            |lua, this, (string, replacement): (LuaString, Value)| {
+                replace_matches(lua, &this.0, string.to_str()?, replacement, 0)
+            },
+        );
+        methods.add_method("split", |lua, this, string: LuaString| {
+            let table = lua.create_table()?;
+            for m in this.0.split(string.to_str()?) {
+                table.raw_push(m)?;
+            }
+            Ok(table)
+        });
+        methods.add_method("split_n", |lua, this, (string, limit): (LuaString, usize)| {
+            let table = lua.create_table()?;
+            for m in this.0.splitn(string.to_str()?, limit) {
+                table.raw_push(m)?;
+            }
+            Ok(table)
+        });
+    }
+}
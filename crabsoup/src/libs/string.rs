@@ -1,10 +1,35 @@
-use crate::wyhash::WyHashSet;
+use crate::{html::markdown::markdown_to_html, wyhash::WyHashSet};
 use base64::Engine;
 use minijinja::Environment;
 use mlua::{prelude::LuaString, Error, Lua, Result, Table, Value};
 use regex::Regex;
+use rust_stemmers::{Algorithm, Stemmer};
 use std::{ops::Deref, sync::LazyLock};
 
+fn stemmer_algorithm(language: &str) -> Option<Algorithm> {
+    Some(match language {
+        "arabic" => Algorithm::Arabic,
+        "danish" => Algorithm::Danish,
+        "dutch" => Algorithm::Dutch,
+        "english" => Algorithm::English,
+        "finnish" => Algorithm::Finnish,
+        "french" => Algorithm::French,
+        "german" => Algorithm::German,
+        "greek" => Algorithm::Greek,
+        "hungarian" => Algorithm::Hungarian,
+        "italian" => Algorithm::Italian,
+        "norwegian" => Algorithm::Norwegian,
+        "portuguese" => Algorithm::Portuguese,
+        "romanian" => Algorithm::Romanian,
+        "russian" => Algorithm::Russian,
+        "spanish" => Algorithm::Spanish,
+        "swedish" => Algorithm::Swedish,
+        "tamil" => Algorithm::Tamil,
+        "turkish" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
 pub fn create_string_table(lua: &Lua) -> Result<Table> {
     let table = lua.create_table()?;
 
@@ -39,6 +64,12 @@ pub fn create_string_table(lua: &Lua) -> Result<Table> {
             Ok(lua.create_string(tmpl.render(lua_env).map_err(Error::runtime)?)?)
         })?,
     )?;
+    table.raw_set(
+        "markdown_to_html",
+        lua.create_function(|lua, str: LuaString| {
+            lua.create_string(markdown_to_html(str.to_str()?))
+        })?,
+    )?;
     table.raw_set(
         "base64_encode",
         lua.create_function(|lua, str: LuaString| {
@@ -102,6 +133,35 @@ pub fn create_string_table(lua: &Lua) -> Result<Table> {
             Ok(str)
         })?,
     )?;
+    table.raw_set(
+        "tokenize",
+        lua.create_function(|lua, (text, language): (LuaString, Option<LuaString>)| {
+            let stemmer = match language.as_ref().map(|x| x.to_str()).transpose()? {
+                None | Some("none") => None,
+                Some(language) => match stemmer_algorithm(language) {
+                    Some(algorithm) => Some(Stemmer::create(algorithm)),
+                    None => {
+                        return Err(Error::runtime(format_args!(
+                            "Unknown stemming language: {language}"
+                        )))
+                    }
+                },
+            };
+
+            let table = lua.create_table()?;
+            for word in text.to_str()?.split(|c: char| !c.is_alphanumeric()) {
+                if word.chars().nth(1).is_none() {
+                    continue;
+                }
+                let word = word.to_lowercase();
+                match &stemmer {
+                    Some(stemmer) => table.raw_push(&*stemmer.stem(&word))?,
+                    None => table.raw_push(word)?,
+                }
+            }
+            Ok(table)
+        })?,
+    )?;
 
     Ok(table)
 }